@@ -1,14 +1,192 @@
+use crate::filters::{PreprocessSettings, Preprocessor, passes_noise_gate};
+use crate::onset::{OnsetDetector, Pluck, PluckTracker, ATTACK_SECONDS};
+use crate::pitch::{AnalysisFrame, PartialFit, PitchDetector, PitchEngine, PitchEstimate, StringPitch, MIN_CONFIDENCE, create_detector, fit_inharmonicity, match_strings};
+use crate::scales::ScaleData;
+use crate::source::{AudioSource, InputGap, LiveSource, WavSource};
+use crate::strobe::{StrobePhase, measure_strobe, strobe_window};
+use crate::wav::{SessionRecorder, WavRecording};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{FromSample, Sample, SizedSample};
 use num_complex::Complex;
-use rustfft::{FftPlanner, num_traits::Zero};
+use rtrb::RingBuffer;
+use rustfft::{Fft, FftPlanner, num_traits::Zero};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const FFT_SIZES: [usize; 5] = [4096, 8192, 16384, 32768, 65536];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WindowFunction {
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    FlatTop,
+}
+
+impl WindowFunction {
+    // All four are cosine-sum windows, so they differ only in their coefficients
+    fn coefficients(&self) -> &'static [f32] {
+        match self {
+            WindowFunction::Hann => &[0.5, 0.5],
+            WindowFunction::Hamming => &[0.54, 0.46],
+            WindowFunction::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            WindowFunction::FlatTop => &[0.215579, 0.416632, 0.277263, 0.083579, 0.006947],
+        }
+    }
+    
+    fn value(&self, i: usize, size: usize) -> f32 {
+        let phase = 2.0 * std::f32::consts::PI * i as f32 / (size - 1) as f32;
+        self.coefficients().iter()
+            .enumerate()
+            .map(|(k, a)| {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                sign * a * (k as f32 * phase).cos()
+            })
+            .sum()
+    }
+}
+
+// Which input channel(s) feed the analysis; `Single` is zero-based
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ChannelSelection {
+    Single(usize),
+    Average,
+    Sum,
+}
+
+impl ChannelSelection {
+    // A saved selection can name a channel the current input doesn't have; it falls back to the
+    // first rather than quietly reading another one
+    pub fn clamped(self, channels: usize) -> Self {
+        match self {
+            ChannelSelection::Single(channel) if channel >= channels => ChannelSelection::Single(0),
+            selection => selection,
+        }
+    }
+    
+    fn downmix(&self, frame: &[f32]) -> f32 {
+        match self {
+            ChannelSelection::Single(channel) => frame[*channel],
+            ChannelSelection::Average => frame.iter().sum::<f32>() / frame.len() as f32,
+            ChannelSelection::Sum => frame.iter().sum(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalysisSettings {
+    pub engine: PitchEngine,
+    pub hop_size: usize,
+    pub fft_size: usize,
+    pub window: WindowFunction,
+    pub channel: ChannelSelection,
+    pub preprocessing: PreprocessSettings,
+    pub polyphonic: bool,
+    pub onset_detection: bool,
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        Self {
+            engine: PitchEngine::Spectral,
+            hop_size: 2048,
+            fft_size: 32768,
+            window: WindowFunction::Hamming,
+            channel: ChannelSelection::Single(0),
+            preprocessing: PreprocessSettings::default(),
+            polyphonic: true,
+            onset_detection: true,
+        }
+    }
+}
+
+// Fixed-size history of the most recent samples. Analysis runs on this sliding window every
+// `hop_size` samples, so readings update quickly without giving up the long window's resolution.
+struct SampleHistory {
+    buffer: Vec<f32>,
+    write_pos: usize,
+    filled: usize,
+}
+
+impl SampleHistory {
+    fn new(size: usize) -> Self {
+        Self {
+            buffer: vec![0.0; size],
+            write_pos: 0,
+            filled: 0,
+        }
+    }
+    
+    fn push(&mut self, sample: f32) {
+        self.buffer[self.write_pos] = sample;
+        self.write_pos = (self.write_pos + 1) % self.buffer.len();
+        self.filled = (self.filled + 1).min(self.buffer.len());
+    }
+    
+    fn is_full(&self) -> bool {
+        self.filled == self.buffer.len()
+    }
+    
+    // Copies the history into `out`, oldest sample first
+    fn copy_ordered(&self, out: &mut [f32]) {
+        let (newest, oldest) = self.buffer.split_at(self.write_pos);
+        out[..oldest.len()].copy_from_slice(oldest);
+        out[oldest.len()..].copy_from_slice(newest);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputDevice {
+    pub host_name: String,
+    pub device_name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioStatus {
+    Starting,
+    Running(String),
+    Error(String),
+}
+
+// Set by the UI to record the live input to `path`; cleared to stop
+#[derive(Clone, Debug)]
+pub struct RecordRequest {
+    pub path: PathBuf,
+    pub scale: ScaleData,
+}
 
 #[derive(Clone, Debug)]
 pub struct AudioState {
     pub frequency_data: Vec<f32>,
     pub peak_frequency: f32,
-    pub sample_rate: u32
+    pub confidence: f32,
+    // Target frequency of every string, set by the UI, and the matching per-string readings
+    pub targets: Vec<f32>,
+    pub string_readings: Vec<Option<StringPitch>>,
+    // The latest pluck and its stabilized pitch, when onset detection is on
+    pub pluck: Option<Pluck>,
+    // Partials of the detected note fitted to a stiff string
+    pub partial_fit: Option<PartialFit>,
+    // The string the strobe follows, set by the UI, and the phase of the signal against it
+    pub strobe_target: Option<f32>,
+    pub strobe: Option<StrobePhase>,
+    pub sample_rate: u32,
+    // Zero until an input is open
+    pub channels: u16,
+    pub settings: AnalysisSettings,
+    pub status: AudioStatus,
+    pub input_device: Option<InputDevice>,
+    pub restart_requested: bool,
+    pub record_request: Option<RecordRequest>,
+    pub record_elapsed: Option<f32>,
+    // Input lost from the current recording because analysis fell behind, in seconds
+    pub record_lost: f32,
+    pub record_error: Option<String>,
 }
 
 impl Default for AudioState {
@@ -16,96 +194,758 @@ impl Default for AudioState {
         Self {
             frequency_data: vec![0.0; 16384],
             peak_frequency: 0.0,
+            confidence: 0.0,
+            targets: Vec::new(),
+            string_readings: Vec::new(),
+            pluck: None,
+            partial_fit: None,
+            strobe_target: None,
+            strobe: None,
             sample_rate: 44100,
+            channels: 0,
+            settings: AnalysisSettings::default(),
+            status: AudioStatus::Starting,
+            input_device: None,
+            restart_requested: false,
+            record_request: None,
+            record_elapsed: None,
+            record_lost: 0.0,
+            record_error: None,
         }
     }
 }
 
+// Every input device on every available host, for the device picker
+pub fn list_input_devices() -> Vec<InputDevice> {
+    let mut devices = Vec::new();
+    for host_id in cpal::available_hosts() {
+        let Ok(host) = cpal::host_from_id(host_id) else {
+            continue;
+        };
+        let Ok(input_devices) = host.input_devices() else {
+            continue;
+        };
+        for device in input_devices {
+            if let Ok(device_name) = device.name() {
+                devices.push(InputDevice {
+                    host_name: host_id.name().to_string(),
+                    device_name,
+                });
+            }
+        }
+    }
+    devices
+}
+
+// How often a failed or fallback input is retried, and how long a stream may go without
+// delivering samples before it is considered dead (some backends stall instead of erroring)
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+
+// A running input stream plus what the supervisor needs to keep an eye on it
+struct InputStream {
+    _stream: cpal::Stream,
+    device_name: String,
+    frames_received: Arc<AtomicUsize>,
+}
+
+// Runs for the lifetime of the app: opens the selected input device, reports the outcome in
+// `AudioState::status`, and reopens whenever the UI asks for a restart (device change, retry)
+// or the stream fails. If the selected device has gone away, the default device is used until
+// it comes back, so unplugging a USB mic doesn't end a tuning session.
 pub fn start_audio_processing(audio_state: Arc<Mutex<AudioState>>) {
-    let host = cpal::default_host();
-    let device = host.default_input_device().expect("No input device available");
-    
-    let config = device.default_input_config().expect("Failed to get default input config");
-    let sample_rate = config.sample_rate().0;
+    loop {
+        let wanted_device = {
+            let mut state = audio_state.lock().unwrap();
+            state.restart_requested = false;
+            state.status = AudioStatus::Starting;
+            state.input_device.clone()
+        };
+        
+        let opened = open_input_stream(wanted_device.as_ref(), &audio_state).or_else(|message| {
+            if wanted_device.is_none() {
+                return Err(message);
+            }
+            eprintln!("{}; falling back to the default input device", message);
+            open_input_stream(None, &audio_state).map_err(|_| message)
+        });
+        
+        match opened {
+            Ok(input) => {
+                let is_fallback = wanted_device.is_some()
+                    && wanted_device.as_ref().map(|d| d.device_name.as_str()) != Some(input.device_name.as_str());
+                let status = if is_fallback {
+                    format!("{} (fallback)", input.device_name)
+                } else {
+                    input.device_name.clone()
+                };
+                audio_state.lock().unwrap().status = AudioStatus::Running(status);
+                
+                supervise_stream(&input, wanted_device.as_ref(), is_fallback, &audio_state);
+            }
+            Err(message) => {
+                eprintln!("Audio input error: {}", message);
+                audio_state.lock().unwrap().status = AudioStatus::Error(message);
+                wait_for_restart(&audio_state, RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+// Blocks until the stream should be rebuilt: a restart was requested, the stream reported an
+// error or stopped delivering samples, the default device changed, or the device we fell back
+// from is available again. Dropping `input` afterwards tears the stream down.
+fn supervise_stream(
+    input: &InputStream,
+    wanted_device: Option<&InputDevice>,
+    is_fallback: bool,
+    audio_state: &Arc<Mutex<AudioState>>,
+) {
+    let mut last_frames = input.frames_received.load(Ordering::Relaxed);
+    let mut last_progress = Instant::now();
+    let mut last_device_check = Instant::now();
     
-    {
-        let mut state = audio_state.lock().unwrap();
-        state.sample_rate = sample_rate;
+    loop {
+        std::thread::sleep(Duration::from_millis(100));
+        
+        if audio_state.lock().unwrap().restart_requested {
+            return;
+        }
+        
+        let frames = input.frames_received.load(Ordering::Relaxed);
+        if frames != last_frames {
+            last_frames = frames;
+            last_progress = Instant::now();
+        } else if last_progress.elapsed() > STALL_TIMEOUT {
+            eprintln!("Input stream on '{}' stopped delivering audio, restarting", input.device_name);
+            return;
+        }
+        
+        if last_device_check.elapsed() > RETRY_INTERVAL {
+            last_device_check = Instant::now();
+            let device_changed = if is_fallback {
+                find_input_device(wanted_device).is_ok()
+            } else if wanted_device.is_none() {
+                let default_name = cpal::default_host().default_input_device().and_then(|d| d.name().ok());
+                default_name.is_some_and(|name| name != input.device_name)
+            } else {
+                false
+            };
+            if device_changed {
+                return;
+            }
+        }
+    }
+}
+
+fn wait_for_restart(audio_state: &Arc<Mutex<AudioState>>, timeout: Duration) {
+    let started = Instant::now();
+    while started.elapsed() < timeout && !audio_state.lock().unwrap().restart_requested {
+        std::thread::sleep(Duration::from_millis(100));
     }
+}
+
+fn find_input_device(input_device: Option<&InputDevice>) -> Result<cpal::Device, String> {
+    let Some(wanted) = input_device else {
+        return cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| "No input device available".to_string());
+    };
+    
+    let host_id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name() == wanted.host_name)
+        .ok_or_else(|| format!("Audio host '{}' is not available", wanted.host_name))?;
+    let host = cpal::host_from_id(host_id).map_err(|e| e.to_string())?;
+    
+    host.input_devices()
+        .map_err(|e| e.to_string())?
+        .find(|device| device.name().map(|name| name == wanted.device_name).unwrap_or(false))
+        .ok_or_else(|| format!("Input device '{}' not found", wanted.device_name))
+}
+
+fn open_input_stream(
+    input_device: Option<&InputDevice>,
+    audio_state: &Arc<Mutex<AudioState>>,
+) -> Result<InputStream, String> {
+    let device = find_input_device(input_device)?;
+    let device_name = device.name().unwrap_or_else(|_| "Unknown device".to_string());
+    
+    let config = device.default_input_config()
+        .map_err(|e| format!("Failed to get input config for '{}': {}", device_name, e))?;
     
     let sample_format = config.sample_format();
     let config: cpal::StreamConfig = config.into();
+    let audio_state = audio_state.clone();
+    let frames_received = Arc::new(AtomicUsize::new(0));
+    let counter = frames_received.clone();
     
-    match sample_format {
-        cpal::SampleFormat::F32 => run::<f32>(&device, config, audio_state),
-        cpal::SampleFormat::I16 => run::<i16>(&device, config, audio_state),
-        cpal::SampleFormat::U16 => run::<u16>(&device, config, audio_state),
-        _ => panic!("Unsupported sample format '{sample_format}'"),
-    }
+    let stream = match sample_format {
+        cpal::SampleFormat::I8 => run::<i8>(&device, config, audio_state, counter),
+        cpal::SampleFormat::I16 => run::<i16>(&device, config, audio_state, counter),
+        cpal::SampleFormat::I32 => run::<i32>(&device, config, audio_state, counter),
+        cpal::SampleFormat::I64 => run::<i64>(&device, config, audio_state, counter),
+        cpal::SampleFormat::U8 => run::<u8>(&device, config, audio_state, counter),
+        cpal::SampleFormat::U16 => run::<u16>(&device, config, audio_state, counter),
+        cpal::SampleFormat::U32 => run::<u32>(&device, config, audio_state, counter),
+        cpal::SampleFormat::U64 => run::<u64>(&device, config, audio_state, counter),
+        cpal::SampleFormat::F32 => run::<f32>(&device, config, audio_state, counter),
+        cpal::SampleFormat::F64 => run::<f64>(&device, config, audio_state, counter),
+        _ => Err(format!("Unsupported sample format '{sample_format}'")),
+    }?;
+    
+    Ok(InputStream {
+        _stream: stream,
+        device_name,
+        frames_received,
+    })
 }
 
-fn run<T>(device: &cpal::Device, config: cpal::StreamConfig, audio_state: Arc<Mutex<AudioState>>)
+// Samples the input callback can queue ahead of the analysis thread (about a second of audio)
+const RING_BUFFER_SECONDS: usize = 1;
+// Gaps in the input the callback can queue; while they're all pending it keeps dropping
+const MAX_QUEUED_GAPS: usize = 64;
+
+fn run<T>(
+    device: &cpal::Device,
+    config: cpal::StreamConfig,
+    audio_state: Arc<Mutex<AudioState>>,
+    frames_received: Arc<AtomicUsize>,
+) -> Result<cpal::Stream, String>
 where
-    T: Sample<Float = f32> + SizedSample,
+    T: SizedSample,
     f32: FromSample<T>,
 {
     let channels = config.channels as usize;
-    let fft_size = 32768;
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(fft_size);
-    
-    let mut input_buffer = vec![Complex::zero(); fft_size];
-    let mut output_buffer = vec![Complex::zero(); fft_size];
-    let mut sample_buffer = Vec::new();
+    let sample_rate = config.sample_rate.0;
+    let (mut producer, consumer) = RingBuffer::new(sample_rate as usize * channels * RING_BUFFER_SECONDS);
+    let (mut gap_producer, gap_consumer) = RingBuffer::new(MAX_QUEUED_GAPS);
+    let mut samples_queued = 0u64;
+    let mut samples_dropped = 0usize;
+    let error_state = audio_state.clone();
     
+    // The callback only hands the interleaved samples over; channel selection and everything
+    // else happens on the analysis thread
     let stream = device.build_input_stream(
         &config,
         move |data: &[T], _: &cpal::InputCallbackInfo| {
-            for sample in data.chunks(channels) {
-                let mono_sample: f32 = sample[0].to_float_sample();
-                sample_buffer.push(mono_sample);
-                
-                if sample_buffer.len() >= fft_size {
-                    for (i, &sample) in sample_buffer.iter().take(fft_size).enumerate() {
-                        input_buffer[i] = Complex::new(sample * hamming_window(i, fft_size), 0.0);
-                    }
-                    
-                    output_buffer.copy_from_slice(&input_buffer);
-                    fft.process(&mut output_buffer);
-                    
-                    let frequency_data: Vec<f32> = output_buffer.iter()
-                        .take(fft_size / 2)
-                        .map(|c| c.norm())
-                        .collect();
-                    
-                    let peak_index = frequency_data.iter()
-                        .enumerate()
-                        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-                        .map(|(index, _)| index)
-                        .unwrap_or(0);
-                    
-                    let peak_frequency = index_to_frequency(peak_index, config.sample_rate.0, fft_size);
-                    
-                    if let Ok(mut state) = audio_state.lock() {
-                        state.frequency_data = frequency_data;
-                        state.peak_frequency = peak_frequency;
-                    }
-                    
-                    sample_buffer.clear();
+            // A full ring means analysis is behind; dropping whole frames beats blocking here. The
+            // gap is announced ahead of the next samples queued, so the analysis thread can
+            // put silence in its place and recordings don't drift.
+            let mut has_room = producer.slots() >= data.len();
+            if has_room && samples_dropped > 0 {
+                let gap = InputGap { position: samples_queued, samples: samples_dropped };
+                has_room = gap_producer.push(gap).is_ok();
+            }
+            if has_room {
+                samples_dropped = 0;
+                for &sample in data {
+                    let _ = producer.push(sample.to_sample::<f32>());
                 }
+                samples_queued += data.len() as u64;
+            } else {
+                samples_dropped += data.len();
+            }
+            frames_received.fetch_add(data.len() / channels, Ordering::Relaxed);
+        },
+        move |err| {
+            // Typically the device was unplugged; have the supervisor rebuild the stream
+            eprintln!("An error occurred on the audio stream: {}", err);
+            if let Ok(mut state) = error_state.lock() {
+                state.status = AudioStatus::Error(err.to_string());
+                state.restart_requested = true;
             }
         },
-        move |err| eprintln!("An error occurred on the audio stream: {}", err),
         None,
-    ).expect("Failed to build input stream");
+    ).map_err(|e| format!("Failed to build input stream: {}", e))?;
+    
+    stream.play().map_err(|e| format!("Failed to start input stream: {}", e))?;
+    
+    // The analysis thread exits by itself once the stream (and with it the producer) is dropped
+    let source = LiveSource::new(consumer, gap_consumer, sample_rate, channels);
+    std::thread::spawn(move || run_analysis(source, &audio_state));
+    
+    Ok(stream)
+}
+
+// Samples taken from a source per read
+const SOURCE_BLOCK_SIZE: usize = 4096;
+
+// Feeds everything `source` delivers through the analysis chain into `audio_state` until the
+// source ends. The live input, WAV files and test signals all take this same path.
+pub fn run_analysis(mut source: impl AudioSource, audio_state: &Mutex<AudioState>) {
+    let mut analyzer = Analyzer::new(source.sample_rate(), source.channels());
+    match audio_state.lock() {
+        Ok(mut state) => {
+            state.sample_rate = source.sample_rate();
+            state.channels = source.channels() as u16;
+            analyzer.targets.clone_from(&state.targets);
+            analyzer.strobe_target = state.strobe_target;
+            analyzer.apply_settings(&state.settings);
+        }
+        Err(_) => return,
+    }
+    let mut buffer = vec![0.0; SOURCE_BLOCK_SIZE];
+    let mut lost_frames = 0;
+    
+    while let Some(count) = source.read(&mut buffer) {
+        if count == 0 {
+            std::thread::sleep(Duration::from_millis(5));
+            continue;
+        }
+        if source.lost_frames() > lost_frames {
+            analyzer.mark_gap(source.lost_frames() - lost_frames);
+            lost_frames = source.lost_frames();
+        }
+        analyzer.push_interleaved(&buffer[..count], audio_state);
+    }
+    
+    // The stream is gone (device change or restart), so a recording can't carry on
+    analyzer.stop_recording(audio_state);
+}
+
+// Owns every buffer the analysis needs so frames are processed without allocating
+// (buffers are only rebuilt when the FFT size or window function is changed)
+struct Analyzer {
+    sample_rate: u32,
+    channel: ChannelSelection,
+    frame: Vec<f32>,
+    frame_pos: usize,
+    preprocess_settings: PreprocessSettings,
+    preprocessor: Preprocessor,
+    planner: FftPlanner<f32>,
+    fft_size: usize,
+    fft: Arc<dyn Fft<f32>>,
+    window_function: WindowFunction,
+    window: Vec<f32>,
+    history: SampleHistory,
+    window_samples: Vec<f32>,
+    fft_buffer: Vec<Complex<f32>>,
+    fft_scratch: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    samples_since_analysis: usize,
+    engine: PitchEngine,
+    detector: Box<dyn PitchDetector>,
+    hop_size: usize,
+    polyphonic: bool,
+    targets: Vec<f32>,
+    string_readings: Vec<Option<StringPitch>>,
+    onset_detection: bool,
+    onset_detector: OnsetDetector,
+    plucks: PluckTracker,
+    samples_since_onset: usize,
+    // Samples analysed since the stream started, the time base the strobe phase is measured on
+    sample_clock: u64,
+    strobe_target: Option<f32>,
+    recorder: Option<SessionRecorder>,
+    record_error: Option<String>,
+}
+
+impl Analyzer {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let settings = AnalysisSettings::default();
+        let fft_size = settings.fft_size;
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(fft_size);
+        let scratch_len = fft.get_inplace_scratch_len();
+        
+        Self {
+            sample_rate,
+            channel: settings.channel,
+            frame: vec![0.0; channels.max(1)],
+            frame_pos: 0,
+            preprocess_settings: settings.preprocessing,
+            preprocessor: Preprocessor::new(&settings.preprocessing, sample_rate),
+            planner,
+            fft_size,
+            fft,
+            window_function: settings.window,
+            window: (0..fft_size).map(|i| settings.window.value(i, fft_size)).collect(),
+            history: SampleHistory::new(fft_size),
+            window_samples: vec![0.0; fft_size],
+            fft_buffer: vec![Complex::zero(); fft_size],
+            fft_scratch: vec![Complex::zero(); scratch_len],
+            magnitudes: vec![0.0; fft_size / 2],
+            samples_since_analysis: 0,
+            engine: settings.engine,
+            detector: create_detector(settings.engine),
+            hop_size: settings.hop_size,
+            polyphonic: settings.polyphonic,
+            targets: Vec::new(),
+            string_readings: Vec::new(),
+            onset_detection: settings.onset_detection,
+            onset_detector: OnsetDetector::new(sample_rate),
+            plucks: PluckTracker::default(),
+            samples_since_onset: 0,
+            sample_clock: 0,
+            strobe_target: None,
+            recorder: None,
+            record_error: None,
+        }
+    }
+    
+    // Ring buffer reads can end mid-frame, so frames are assembled one sample at a time
+    fn push_interleaved(&mut self, samples: &[f32], audio_state: &Mutex<AudioState>) {
+        for &sample in samples {
+            self.frame[self.frame_pos] = sample;
+            self.frame_pos += 1;
+            
+            if self.frame_pos == self.frame.len() {
+                self.frame_pos = 0;
+                if let Some(recorder) = &mut self.recorder {
+                    if let Err(message) = recorder.write_frame(&self.frame) {
+                        self.recorder = None;
+                        self.record_error = Some(message);
+                    }
+                }
+                let mono_sample = self.channel.downmix(&self.frame);
+                self.push_sample(mono_sample, audio_state);
+            }
+        }
+    }
+    
+    fn push_sample(&mut self, sample: f32, audio_state: &Mutex<AudioState>) {
+        let sample = self.preprocessor.process(sample);
+        if self.onset_detection && self.onset_detector.process(sample) {
+            self.plucks.start();
+            self.samples_since_onset = 0;
+        } else {
+            self.samples_since_onset = self.samples_since_onset.saturating_add(1);
+        }
+        
+        self.history.push(sample);
+        self.sample_clock += 1;
+        self.samples_since_analysis += 1;
+        
+        if self.history.is_full() && self.samples_since_analysis >= self.hop_size {
+            self.samples_since_analysis = 0;
+            self.analyze(audio_state);
+        }
+    }
+    
+    fn analyze(&mut self, audio_state: &Mutex<AudioState>) {
+        self.history.copy_ordered(&mut self.window_samples);
+        
+        let gate_open = passes_noise_gate(&self.window_samples, &self.preprocess_settings);
+        let estimate = if gate_open {
+            for ((bin, &sample), &weight) in self.fft_buffer.iter_mut().zip(&self.window_samples).zip(&self.window) {
+                *bin = Complex::new(sample * weight, 0.0);
+            }
+            self.fft.process_with_scratch(&mut self.fft_buffer, &mut self.fft_scratch);
+            
+            for (magnitude, bin) in self.magnitudes.iter_mut().zip(&self.fft_buffer) {
+                *magnitude = bin.norm();
+            }
+            
+            if self.polyphonic {
+                match_strings(&self.magnitudes, self.sample_rate, self.fft_size, &self.targets, &mut self.string_readings);
+            } else {
+                self.string_readings.clear();
+            }
+            
+            self.detector.detect(&AnalysisFrame {
+                samples: &self.window_samples,
+                spectrum: &self.magnitudes,
+                sample_rate: self.sample_rate,
+                fft_size: self.fft_size,
+            })
+        } else {
+            // Gated: publish silence rather than analysing room noise
+            self.magnitudes.fill(0.0);
+            self.string_readings.clear();
+            PitchEstimate::default()
+        };
+        
+        if let Some(recorder) = &mut self.recorder {
+            recorder.add_pitch(estimate.frequency, estimate.confidence);
+        }
+        
+        let partial_fit = if estimate.confidence >= MIN_CONFIDENCE {
+            fit_inharmonicity(&self.magnitudes, self.sample_rate, self.fft_size, estimate.frequency)
+        } else {
+            None
+        };
+        
+        let strobe = self.strobe_target.filter(|_| gate_open).map(|target| {
+            let length = strobe_window(target, self.sample_rate).max(self.hop_size).min(self.window_samples.len());
+            let samples = &self.window_samples[self.window_samples.len() - length..];
+            measure_strobe(samples, self.sample_clock, self.sample_rate, target)
+        });
+        
+        // Frames whose analysed span still reaches back into the attack (or the note before it)
+        // would drag the pluck's pitch around, so leave them out until it has moved past it. That
+        // is the whole FFT window for the spectral engine but only a few periods for YIN.
+        let attack_samples = (ATTACK_SECONDS * self.sample_rate as f32) as usize;
+        let span = self.detector.span(self.sample_rate, self.fft_size);
+        if self.onset_detection && self.samples_since_onset >= attack_samples + span {
+            self.plucks.add_frame(&estimate);
+        }
+        
+        let (settings, record_change) = match audio_state.lock() {
+            Ok(mut state) => {
+                // Reuse the shared allocation rather than handing over a fresh Vec every frame
+                state.frequency_data.clear();
+                state.frequency_data.extend_from_slice(&self.magnitudes);
+                state.peak_frequency = estimate.frequency;
+                state.confidence = estimate.confidence;
+                state.partial_fit = partial_fit;
+                state.string_readings.clone_from(&self.string_readings);
+                state.pluck = if self.onset_detection { self.plucks.pluck() } else { None };
+                state.strobe = strobe;
+                self.targets.clone_from(&state.targets);
+                self.strobe_target = state.strobe_target;
+                
+                if let Some(message) = self.record_error.take() {
+                    state.record_request = None;
+                    state.record_error = Some(message);
+                }
+                state.record_elapsed = self.recorder.as_ref().map(SessionRecorder::elapsed_secs);
+                state.record_lost = self.recorder.as_ref().map_or(0.0, SessionRecorder::lost_secs);
+                let record_change = match (&state.record_request, &self.recorder) {
+                    (Some(request), None) => Some(Some(request.clone())),
+                    (None, Some(_)) => Some(None),
+                    _ => None,
+                };
+                (state.settings, record_change)
+            }
+            Err(_) => return,
+        };
+        
+        // File I/O happens outside the lock so the UI never waits on the disk
+        match record_change {
+            Some(Some(request)) => self.start_recording(request),
+            Some(None) => self.finish_recording(),
+            None => {}
+        }
+        self.apply_settings(&settings);
+    }
+    
+    // The samples about to be pushed are silence standing in for `frames` of lost input
+    fn mark_gap(&mut self, frames: usize) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.add_gap(frames);
+        }
+    }
+    
+    fn start_recording(&mut self, request: RecordRequest) {
+        let channels = self.frame.len() as u16;
+        match SessionRecorder::create(&request.path, self.sample_rate, channels, request.scale) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(message) => self.record_error = Some(message),
+        }
+    }
+    
+    fn finish_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(message) = recorder.finish() {
+                self.record_error = Some(message);
+            }
+        }
+    }
+    
+    fn stop_recording(&mut self, audio_state: &Mutex<AudioState>) {
+        if self.recorder.is_none() {
+            return;
+        }
+        self.finish_recording();
+        
+        if let Ok(mut state) = audio_state.lock() {
+            state.record_request = None;
+            state.record_elapsed = None;
+            state.record_error = Some(self.record_error.take()
+                .unwrap_or_else(|| "Recording stopped because the input stream closed".to_string()));
+        }
+    }
+    
+    // Picks up changes made in the UI between frames
+    fn apply_settings(&mut self, settings: &AnalysisSettings) {
+        self.channel = settings.channel.clamped(self.frame.len());
+        self.polyphonic = settings.polyphonic;
+        self.onset_detection = settings.onset_detection;
+        if settings.preprocessing != self.preprocess_settings {
+            self.preprocess_settings = settings.preprocessing;
+            self.preprocessor = Preprocessor::new(&self.preprocess_settings, self.sample_rate);
+        }
+        if settings.engine != self.engine {
+            self.engine = settings.engine;
+            self.detector = create_detector(self.engine);
+        }
+        if settings.fft_size != self.fft_size {
+            self.resize(settings.fft_size);
+        }
+        if settings.window != self.window_function {
+            self.window_function = settings.window;
+            self.rebuild_window();
+        }
+        self.hop_size = settings.hop_size.clamp(1, self.fft_size);
+    }
+    
+    // Re-plans the FFT for a new size while the stream keeps running. The most recent samples
+    // are carried over so the next frame is analysed without waiting for a full new window.
+    fn resize(&mut self, fft_size: usize) {
+        let mut history = SampleHistory::new(fft_size);
+        let keep = self.window_samples.len().min(fft_size);
+        for &sample in &self.window_samples[self.window_samples.len() - keep..] {
+            history.push(sample);
+        }
+        
+        self.fft_size = fft_size;
+        self.fft = self.planner.plan_fft_forward(fft_size);
+        self.history = history;
+        self.window_samples = vec![0.0; fft_size];
+        self.fft_buffer = vec![Complex::zero(); fft_size];
+        self.fft_scratch = vec![Complex::zero(); self.fft.get_inplace_scratch_len()];
+        self.magnitudes = vec![0.0; fft_size / 2];
+        self.rebuild_window();
+    }
     
-    stream.play().expect("Failed to play stream");
+    fn rebuild_window(&mut self) {
+        let (window_function, fft_size) = (self.window_function, self.fft_size);
+        self.window.clear();
+        self.window.extend((0..fft_size).map(|i| window_function.value(i, fft_size)));
+    }
+    
+    // Starts over as if no audio had been seen: empty history, filters at rest, no partially
+    // assembled frame and no pluck
+    fn reset(&mut self) {
+        self.preprocessor = Preprocessor::new(&self.preprocess_settings, self.sample_rate);
+        self.history = SampleHistory::new(self.fft_size);
+        self.frame_pos = 0;
+        self.samples_since_analysis = 0;
+        self.onset_detector = OnsetDetector::new(self.sample_rate);
+        self.plucks = PluckTracker::default();
+        self.samples_since_onset = 0;
+    }
     
-    std::thread::park();
+    // Feeds a whole source through the same chain as the live input, onsets and plucks included
+    fn load(&mut self, source: &mut impl AudioSource, audio_state: &Mutex<AudioState>) {
+        let mut buffer = vec![0.0; SOURCE_BLOCK_SIZE];
+        while let Some(count) = source.read(&mut buffer) {
+            self.push_interleaved(&buffer[..count], audio_state);
+        }
+    }
+}
+
+// Audio analysed ahead of the window when scrubbing a recording, so the high-pass and hum
+// notches have settled by the time the window starts
+const FILTER_WARMUP_SECONDS: f32 = 0.25;
+// With onset detection on, also this much further back, so a pluck still ringing at the
+// position is followed from its onset just as it would have been live
+const PLUCK_LOOKBACK_SECONDS: f32 = 3.0;
+
+// Runs the live analysis chain over a WAV recording at a chosen point in time. Results go into
+// the recording's own `AudioState`, which the UI displays in place of the live one. The work
+// happens on a thread of its own so scrubbing never waits for it.
+pub struct RecordingAnalyzer {
+    pub recording: Arc<WavRecording>,
+    pub position: f32,
+    pub audio_state: Arc<Mutex<AudioState>>,
+    requests: Sender<RecordingRequest>,
+    requested: Option<RecordingRequest>,
 }
 
-fn hamming_window(i: usize, size: usize) -> f32 {
-    0.54 - 0.46 * (2.0 * std::f32::consts::PI * i as f32 / (size - 1) as f32).cos()
+// A point in a recording to analyse, and what to analyse it against
+#[derive(Clone, PartialEq)]
+struct RecordingRequest {
+    position: f32,
+    settings: AnalysisSettings,
+    targets: Vec<f32>,
+    strobe_target: Option<f32>,
+}
+
+impl RecordingAnalyzer {
+    pub fn new(recording: WavRecording) -> Self {
+        let audio_state = Arc::new(Mutex::new(AudioState {
+            sample_rate: recording.sample_rate,
+            channels: recording.channels as u16,
+            status: AudioStatus::Running(recording.file_name()),
+            ..Default::default()
+        }));
+        let recording = Arc::new(recording);
+        
+        // The worker exits once this analyzer, and with it the sending end, is dropped
+        let (requests, receiver) = mpsc::channel();
+        let worker = RecordingWorker::new(recording.clone(), audio_state.clone());
+        std::thread::spawn(move || worker.run(receiver));
+        
+        Self {
+            recording,
+            position: 0.0,
+            audio_state,
+            requests,
+            requested: None,
+        }
+    }
+    
+    // Asks for the window ending at `position` seconds to be analysed against the given string
+    // targets (and strobe target), unless nothing has changed since the last call
+    pub fn update(&mut self, settings: &AnalysisSettings, targets: &[f32], strobe_target: Option<f32>) {
+        let request = RecordingRequest {
+            position: self.position,
+            settings: *settings,
+            targets: targets.to_vec(),
+            strobe_target,
+        };
+        if self.requested.as_ref() == Some(&request) {
+            return;
+        }
+        let _ = self.requests.send(request.clone());
+        self.requested = Some(request);
+    }
+}
+
+struct RecordingWorker {
+    recording: Arc<WavRecording>,
+    audio_state: Arc<Mutex<AudioState>>,
+    analyzer: Analyzer,
+}
+
+impl RecordingWorker {
+    fn new(recording: Arc<WavRecording>, audio_state: Arc<Mutex<AudioState>>) -> Self {
+        Self {
+            analyzer: Analyzer::new(recording.sample_rate, recording.channels),
+            recording,
+            audio_state,
+        }
+    }
+    
+    fn run(mut self, requests: Receiver<RecordingRequest>) {
+        // Only the latest request matters: positions dragged past while busy are skipped
+        while let Ok(mut request) = requests.recv() {
+            while let Ok(newer) = requests.try_recv() {
+                request = newer;
+            }
+            self.analyze(&request);
+        }
+    }
+    
+    fn analyze(&mut self, request: &RecordingRequest) {
+        {
+            let mut state = self.audio_state.lock().unwrap();
+            state.settings = request.settings;
+            state.targets.clone_from(&request.targets);
+            state.strobe_target = request.strobe_target;
+        }
+        self.analyzer.targets.clone_from(&request.targets);
+        self.analyzer.strobe_target = request.strobe_target;
+        self.analyzer.apply_settings(&request.settings);
+        
+        let sample_rate = self.recording.sample_rate as f32;
+        let end = ((request.position.max(0.0) * sample_rate) as usize).min(self.recording.frames());
+        let lookback = if request.settings.onset_detection { PLUCK_LOOKBACK_SECONDS } else { 0.0 };
+        let warmup = ((FILTER_WARMUP_SECONDS + lookback) * sample_rate) as usize;
+        let start = end.saturating_sub(self.analyzer.fft_size + warmup);
+        
+        self.analyzer.reset();
+        // Counting from the start of the file keeps the strobe phase tied to the recording's time
+        self.analyzer.sample_clock = start as u64;
+        self.analyzer.load(&mut WavSource::new(&self.recording, start, end), &self.audio_state);
+        // The window must end exactly at the position, but a frame that already does isn't
+        // counted twice
+        if self.analyzer.samples_since_analysis > 0 || !self.analyzer.history.is_full() {
+            self.analyzer.analyze(&self.audio_state);
+        }
+    }
 }
 
 pub fn play_notes_descending(frequencies: &[f32]) {
@@ -248,7 +1088,427 @@ fn play_notes_descending_internal(frequencies: &[f32]) -> Result<(), cpal::PlayS
     
     Ok(())
 }
-
-fn index_to_frequency(index: usize, sample_rate: u32, fft_size: usize) -> f32 {
-    index as f32 * sample_rate as f32 / (fft_size as f32)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::{SyntheticSignal, SyntheticSource, cents_between};
+    
+    fn analyze_source(source: impl AudioSource) -> AudioState {
+        let audio_state = Mutex::new(AudioState::default());
+        run_analysis(source, &audio_state);
+        audio_state.into_inner().unwrap()
+    }
+    
+    // Detected pitch must land within this many cents of the string's true fundamental
+    const SPECTRAL_TOLERANCE_CENTS: f32 = 1.5;
+    const YIN_TOLERANCE_CENTS: f32 = 5.0;
+    
+    // 30 Hz to 2 kHz, covering the lowest bass strings to well above a lyre's top string
+    const TEST_FREQUENCIES: [f32; 14] = [
+        30.0, 41.2, 55.0, 73.4, 98.0, 130.8, 196.0, 261.6, 392.0, 523.3, 784.0, 1046.5, 1568.0, 2000.0,
+    ];
+    
+    // Runs every test frequency through the full analysis chain with the given engine and
+    // reports all misses at once, so a regression shows its whole extent
+    fn assert_pitch_accuracy(engine: PitchEngine, tolerance_cents: f32, signal_for: impl Fn(f32) -> SyntheticSignal) {
+        let failures: Vec<String> = TEST_FREQUENCIES.iter()
+            .filter_map(|&frequency| {
+                let signal = signal_for(frequency);
+                // The fundamental of a stiff string is itself stretched by sqrt(1 + B)
+                let expected = signal.frequency
+                    * 2f32.powf(signal.detune_cents / 1200.0)
+                    * (1.0 + signal.inharmonicity).sqrt();
+                
+                let audio_state = Mutex::new(AudioState::default());
+                {
+                    let mut state = audio_state.lock().unwrap();
+                    state.settings.engine = engine;
+                    // Only the last frame is checked, so skip most of the intermediate ones
+                    state.settings.hop_size = 16384;
+                }
+                run_analysis(SyntheticSource::new(&signal, 44100, 1), &audio_state);
+                let state = audio_state.into_inner().unwrap();
+                
+                let cents = cents_between(state.peak_frequency, expected);
+                if cents.abs() > tolerance_cents || state.confidence < 0.5 {
+                    Some(format!("{} Hz: detected {:.3} Hz ({:+.2} cents, confidence {:.2})",
+                                 frequency, state.peak_frequency, cents, state.confidence))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        
+        assert!(failures.is_empty(), "{:?} engine missed:\n{}", engine, failures.join("\n"));
+    }
+    
+    fn plucked(frequency: f32) -> SyntheticSignal {
+        SyntheticSignal::plucked(frequency)
+    }
+    
+    fn inharmonic(frequency: f32) -> SyntheticSignal {
+        SyntheticSignal {
+            inharmonicity: 2e-4,
+            ..SyntheticSignal::plucked(frequency)
+        }
+    }
+    
+    // Fundamental at a tenth of its usual level, well under the 2nd and 3rd harmonics
+    fn weak_fundamental(frequency: f32) -> SyntheticSignal {
+        let mut signal = SyntheticSignal::plucked(frequency);
+        signal.harmonics[0] *= 0.1;
+        signal
+    }
+    
+    fn noisy(frequency: f32) -> SyntheticSignal {
+        SyntheticSignal {
+            noise: 0.02,
+            ..SyntheticSignal::plucked(frequency)
+        }
+    }
+    
+    fn detuned(frequency: f32) -> SyntheticSignal {
+        SyntheticSignal {
+            detune_cents: -13.0,
+            ..SyntheticSignal::plucked(frequency)
+        }
+    }
+    
+    fn fast_decay(frequency: f32) -> SyntheticSignal {
+        SyntheticSignal {
+            decay: 3.0,
+            ..SyntheticSignal::plucked(frequency)
+        }
+    }
+    
+    #[test]
+    fn test_spectral_plucked_strings() {
+        assert_pitch_accuracy(PitchEngine::Spectral, SPECTRAL_TOLERANCE_CENTS, plucked);
+    }
+    
+    #[test]
+    fn test_spectral_inharmonic_strings() {
+        assert_pitch_accuracy(PitchEngine::Spectral, SPECTRAL_TOLERANCE_CENTS, inharmonic);
+    }
+    
+    #[test]
+    fn test_spectral_weak_fundamental() {
+        assert_pitch_accuracy(PitchEngine::Spectral, SPECTRAL_TOLERANCE_CENTS, weak_fundamental);
+    }
+    
+    #[test]
+    fn test_spectral_noisy_strings() {
+        assert_pitch_accuracy(PitchEngine::Spectral, SPECTRAL_TOLERANCE_CENTS, noisy);
+    }
+    
+    #[test]
+    fn test_spectral_detuned_strings() {
+        assert_pitch_accuracy(PitchEngine::Spectral, SPECTRAL_TOLERANCE_CENTS, detuned);
+    }
+    
+    #[test]
+    fn test_spectral_fast_decay() {
+        assert_pitch_accuracy(PitchEngine::Spectral, SPECTRAL_TOLERANCE_CENTS, fast_decay);
+    }
+    
+    #[test]
+    fn test_yin_plucked_strings() {
+        assert_pitch_accuracy(PitchEngine::Yin, YIN_TOLERANCE_CENTS, plucked);
+    }
+    
+    #[test]
+    fn test_yin_inharmonic_strings() {
+        assert_pitch_accuracy(PitchEngine::Yin, YIN_TOLERANCE_CENTS, inharmonic);
+    }
+    
+    #[test]
+    fn test_yin_weak_fundamental() {
+        assert_pitch_accuracy(PitchEngine::Yin, YIN_TOLERANCE_CENTS, weak_fundamental);
+    }
+    
+    #[test]
+    fn test_yin_noisy_strings() {
+        assert_pitch_accuracy(PitchEngine::Yin, YIN_TOLERANCE_CENTS, noisy);
+    }
+    
+    #[test]
+    fn test_yin_detuned_strings() {
+        assert_pitch_accuracy(PitchEngine::Yin, YIN_TOLERANCE_CENTS, detuned);
+    }
+    
+    // E Dorian on a 7-string lyre (E3 up to D4)
+    const LYRE_TARGETS: [f32; 7] = [164.81, 185.00, 196.00, 220.00, 246.94, 277.18, 293.66];
+    
+    fn string_readings(signals: &[SyntheticSignal], targets: &[f32]) -> Vec<Option<StringPitch>> {
+        let audio_state = Mutex::new(AudioState {
+            targets: targets.to_vec(),
+            ..Default::default()
+        });
+        run_analysis(SyntheticSource::mix(signals, 44100, 1), &audio_state);
+        audio_state.into_inner().unwrap().string_readings
+    }
+    
+    #[test]
+    fn test_polyphonic_strum_reports_each_string() {
+        let strum = [
+            SyntheticSignal { detune_cents: 4.0, ..SyntheticSignal::plucked(164.81) },
+            SyntheticSignal { detune_cents: -9.0, ..SyntheticSignal::plucked(196.00) },
+            SyntheticSignal { detune_cents: 2.0, ..SyntheticSignal::plucked(246.94) },
+        ];
+        let readings = string_readings(&strum, &LYRE_TARGETS);
+        
+        assert_eq!(readings.len(), LYRE_TARGETS.len());
+        for (index, expected_cents) in [(0, 4.0), (2, -9.0), (4, 2.0)] {
+            let reading = readings[index].unwrap_or_else(|| panic!("string {} not detected", index));
+            assert!((reading.cents - expected_cents).abs() < 1.0,
+                   "string {} read {:+.2} cents, expected {:+.1}", index, reading.cents, expected_cents);
+        }
+        for index in [1, 3, 5, 6] {
+            assert!(readings[index].is_none(), "silent string {} reported as {:?}", index, readings[index]);
+        }
+    }
+    
+    #[test]
+    fn test_polyphonic_ignores_harmonics_of_lower_strings() {
+        let targets = [110.0, 165.0, 220.0, 330.0];
+        let readings = string_readings(&[SyntheticSignal::plucked(110.0)], &targets);
+        
+        assert!(readings[0].is_some());
+        assert!(readings[1..].iter().all(Option::is_none), "harmonics reported as strings: {:?}", readings);
+    }
+    
+    #[test]
+    fn test_inharmonicity_is_measured() {
+        for (frequency, inharmonicity) in [(110.0, 3e-4), (196.0, 1e-4), (440.0, 5e-5)] {
+            let signal = SyntheticSignal {
+                inharmonicity,
+                ..SyntheticSignal::plucked(frequency)
+            };
+            let fit = analyze_source(SyntheticSource::new(&signal, 44100, 1)).partial_fit.expect("no partials fitted");
+            
+            assert!((fit.inharmonicity - inharmonicity).abs() < inharmonicity * 0.1,
+                   "{} Hz: fitted B = {:e}, expected {:e}", frequency, fit.inharmonicity, inharmonicity);
+            let expected_fundamental = frequency * (1.0 + inharmonicity).sqrt();
+            assert!(cents_between(fit.fundamental, expected_fundamental).abs() < 0.5);
+            // Stretched upper partials pull the perceived pitch sharp of the fundamental
+            assert!(fit.perceived > fit.fundamental);
+        }
+    }
+    
+    #[test]
+    fn test_harmonic_string_has_no_inharmonicity() {
+        let fit = analyze_source(SyntheticSource::new(&SyntheticSignal::plucked(146.83), 44100, 1))
+            .partial_fit
+            .expect("no partials fitted");
+        
+        assert!(fit.inharmonicity.abs() < 5e-6, "fitted B = {:e}", fit.inharmonicity);
+        assert!(cents_between(fit.perceived, fit.fundamental).abs() < 0.2);
+    }
+    
+    // The same string plucked twice, retuned in between
+    fn two_plucks() -> [SyntheticSignal; 2] {
+        let first = SyntheticSignal {
+            detune_cents: -20.0,
+            decay: 4.0,
+            start_secs: 0.2,
+            duration_secs: 2.4,
+            ..SyntheticSignal::plucked(196.0)
+        };
+        let second = SyntheticSignal {
+            detune_cents: 6.0,
+            start_secs: 1.2,
+            duration_secs: 2.4,
+            ..SyntheticSignal::plucked(196.0)
+        };
+        [first, second]
+    }
+    
+    #[test]
+    fn test_onset_stabilizes_pitch_per_pluck() {
+        let [first, second] = two_plucks();
+        
+        // Once the second pluck has a reading it is the new pitch, never the old one carried
+        // over in the analysis window
+        for seconds in [1.4, 1.7, 2.0, 2.2] {
+            let truncated = [first.clone(), second.clone()].map(|signal| SyntheticSignal { duration_secs: seconds, ..signal });
+            let state = analyze_source(SyntheticSource::mix(&truncated, 44100, 1));
+            let pluck = state.pluck.expect("no pluck detected");
+            assert_eq!(pluck.id, 2);
+            if pluck.frames > 0 {
+                let cents = cents_between(pluck.frequency, 196.0);
+                assert!((cents - 6.0).abs() < 2.0, "pluck read {:+.2} cents at {} s", cents, seconds);
+            }
+        }
+        
+        // YIN only looks at the last few periods, so its reading arrives soon after the attack
+        let truncated = [first.clone(), second.clone()].map(|signal| SyntheticSignal { duration_secs: 1.4, ..signal });
+        let audio_state = Mutex::new(AudioState::default());
+        audio_state.lock().unwrap().settings.engine = PitchEngine::Yin;
+        run_analysis(SyntheticSource::mix(&truncated, 44100, 1), &audio_state);
+        let pluck = audio_state.into_inner().unwrap().pluck.expect("no pluck detected");
+        assert_eq!(pluck.id, 2);
+        assert!(pluck.frames > 0);
+        let cents = cents_between(pluck.frequency, 196.0);
+        assert!((cents - 6.0).abs() < YIN_TOLERANCE_CENTS, "YIN pluck read {:+.2} cents", cents);
+        
+        let state = analyze_source(SyntheticSource::mix(&[first, second], 44100, 1));
+        let pluck = state.pluck.expect("no pluck detected");
+        assert_eq!(pluck.id, 2);
+        assert!(pluck.ringing);
+        assert!(pluck.frames > 3);
+        let cents = cents_between(pluck.frequency, 196.0);
+        assert!((cents - 6.0).abs() < 2.0, "pluck read {:+.2} cents", cents);
+    }
+    
+    #[test]
+    fn test_recording_reanalysis_tracks_plucks() {
+        let mut source = SyntheticSource::mix(&two_plucks(), 44100, 1);
+        let mut samples = Vec::new();
+        let mut buffer = vec![0.0; SOURCE_BLOCK_SIZE];
+        while let Some(count) = source.read(&mut buffer) {
+            samples.extend_from_slice(&buffer[..count]);
+        }
+        
+        let recording = Arc::new(WavRecording {
+            path: PathBuf::from("plucks.wav"),
+            sample_rate: 44100,
+            channels: 1,
+            samples,
+        });
+        let audio_state = Arc::new(Mutex::new(AudioState::default()));
+        let mut worker = RecordingWorker::new(recording, audio_state.clone());
+        worker.analyze(&RecordingRequest {
+            position: 2.2,
+            settings: AnalysisSettings { onset_detection: true, ..Default::default() },
+            targets: Vec::new(),
+            strobe_target: None,
+        });
+        
+        // Scrubbing shows the pluck the live tuner would have shown at that point
+        let pluck = audio_state.lock().unwrap().pluck.expect("no pluck detected");
+        assert_eq!(pluck.id, 2);
+        assert!(pluck.frames > 0);
+        let cents = cents_between(pluck.frequency, 196.0);
+        assert!((cents - 6.0).abs() < 2.0, "pluck read {:+.2} cents", cents);
+    }
+    
+    #[test]
+    fn test_polyphonic_detects_octave_strings_together() {
+        let targets = [110.0, 165.0, 220.0, 330.0];
+        let readings = string_readings(&[SyntheticSignal::plucked(110.0), SyntheticSignal::plucked(220.0)], &targets);
+        
+        assert!(readings[0].is_some() && readings[2].is_some(), "{:?}", readings);
+        assert!(readings[1].is_none() && readings[3].is_none(), "{:?}", readings);
+    }
+    
+    #[test]
+    fn test_synthetic_source_fills_audio_state() {
+        let signal = SyntheticSignal {
+            frequency: 196.0,
+            ..Default::default()
+        };
+        let state = analyze_source(SyntheticSource::new(&signal, 48000, 2));
+        
+        assert_eq!(state.sample_rate, 48000);
+        assert_eq!(state.channels, 2);
+        assert_eq!(state.frequency_data.len(), AnalysisSettings::default().fft_size / 2);
+        assert!(cents_between(state.peak_frequency, 196.0).abs() < 1.0,
+               "detected {} Hz for 196 Hz", state.peak_frequency);
+        assert!(state.confidence > 0.5);
+    }
+    
+    #[test]
+    fn test_channel_selection_reads_only_that_channel() {
+        let signal = SyntheticSignal {
+            frequency: 196.0,
+            ..Default::default()
+        };
+        let mut source = SyntheticSource::new(&signal, 44100, 1);
+        let mut samples = Vec::new();
+        let mut buffer = vec![0.0; SOURCE_BLOCK_SIZE];
+        while let Some(count) = source.read(&mut buffer) {
+            samples.extend(buffer[..count].iter().flat_map(|&sample| [0.0, sample]));
+        }
+        // A stereo input with the string on the second channel only
+        let recording = WavRecording {
+            path: PathBuf::from("right.wav"),
+            sample_rate: 44100,
+            channels: 2,
+            samples,
+        };
+        let analyze_channel = |channel| {
+            let audio_state = Mutex::new(AudioState::default());
+            audio_state.lock().unwrap().settings.channel = channel;
+            run_analysis(WavSource::new(&recording, 0, recording.frames()), &audio_state);
+            audio_state.into_inner().unwrap()
+        };
+        
+        let right = analyze_channel(ChannelSelection::Single(1));
+        assert!(cents_between(right.peak_frequency, 196.0).abs() < 1.0,
+               "detected {} Hz for 196 Hz", right.peak_frequency);
+        assert!(right.confidence > 0.5);
+        
+        let left = analyze_channel(ChannelSelection::Single(0));
+        assert!(left.confidence < MIN_CONFIDENCE, "silent channel read {} Hz", left.peak_frequency);
+        
+        // A third channel saved from another device falls back to the first, not the last
+        assert_eq!(ChannelSelection::Single(2).clamped(2), ChannelSelection::Single(0));
+        assert_eq!(ChannelSelection::Single(1).clamped(2), ChannelSelection::Single(1));
+        assert_eq!(ChannelSelection::Average.clamped(1), ChannelSelection::Average);
+        let stale = analyze_channel(ChannelSelection::Single(2));
+        assert!(stale.confidence < MIN_CONFIDENCE, "stale selection read {} Hz", stale.peak_frequency);
+    }
+    
+    #[test]
+    fn test_synthetic_analysis_is_deterministic() {
+        let signal = SyntheticSignal {
+            frequency: 330.0,
+            detune_cents: 7.0,
+            noise: 0.2,
+            ..Default::default()
+        };
+        let first = analyze_source(SyntheticSource::new(&signal, 44100, 1));
+        let second = analyze_source(SyntheticSource::new(&signal, 44100, 1));
+        
+        assert_eq!(first.peak_frequency, second.peak_frequency);
+        assert_eq!(first.confidence, second.confidence);
+        assert_eq!(first.frequency_data, second.frequency_data);
+        assert!((cents_between(first.peak_frequency, 330.0) - 7.0).abs() < 1.0);
+    }
+    
+    #[test]
+    fn test_wav_source_matches_synthetic_source() {
+        let signal = SyntheticSignal {
+            frequency: 110.0,
+            noise: 0.05,
+            ..Default::default()
+        };
+        
+        let path = std::env::temp_dir().join(format!("lyretune-source-test-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        let mut source = SyntheticSource::new(&signal, 44100, 2);
+        let mut buffer = vec![0.0; 1000];
+        while let Some(count) = source.read(&mut buffer) {
+            for &sample in &buffer[..count] {
+                writer.write_sample(sample).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        
+        let recording = WavRecording::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        
+        let from_file = analyze_source(WavSource::new(&recording, 0, recording.frames()));
+        let from_generator = analyze_source(SyntheticSource::new(&signal, 44100, 2));
+        
+        assert_eq!(from_file.peak_frequency, from_generator.peak_frequency);
+        assert_eq!(from_file.frequency_data, from_generator.frequency_data);
+        assert_eq!(from_file.channels, 2);
+    }
+}
@@ -0,0 +1,229 @@
+// Pre-processing applied to the mono signal before it reaches the FFT/pitch detectors:
+// a high-pass for room rumble, optional mains-hum notches, and an RMS noise gate.
+
+use serde::{Deserialize, Serialize};
+
+// Q of the hum notches: narrow enough to leave a string sitting a semitone away untouched
+const HUM_NOTCH_Q: f64 = 30.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum HumFrequency {
+    Off,
+    Hz50,
+    Hz60,
+}
+
+impl HumFrequency {
+    fn hz(&self) -> Option<f64> {
+        match self {
+            HumFrequency::Off => None,
+            HumFrequency::Hz50 => Some(50.0),
+            HumFrequency::Hz60 => Some(60.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreprocessSettings {
+    pub high_pass_enabled: bool,
+    pub high_pass_hz: f32,
+    pub hum_frequency: HumFrequency,
+    pub hum_harmonics: usize,
+    pub noise_gate_enabled: bool,
+    pub noise_gate_db: f32,
+}
+
+impl Default for PreprocessSettings {
+    fn default() -> Self {
+        Self {
+            high_pass_enabled: true,
+            high_pass_hz: 25.0,
+            hum_frequency: HumFrequency::Off,
+            hum_harmonics: 4,
+            noise_gate_enabled: false,
+            noise_gate_db: -60.0,
+        }
+    }
+}
+
+// Second-order IIR section (RBJ audio EQ cookbook), transposed direct form II. Coefficients
+// and state are kept in f64 because low cutoffs at 44.1/48 kHz are badly conditioned in f32.
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn from_coefficients(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+    
+    fn high_pass(sample_rate: f64, cutoff: f64, q: f64) -> Self {
+        let omega = 2.0 * std::f64::consts::PI * cutoff / sample_rate;
+        let alpha = omega.sin() / (2.0 * q);
+        let cos = omega.cos();
+        Self::from_coefficients(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+    
+    fn notch(sample_rate: f64, center: f64, q: f64) -> Self {
+        let omega = 2.0 * std::f64::consts::PI * center / sample_rate;
+        let alpha = omega.sin() / (2.0 * q);
+        let cos = omega.cos();
+        Self::from_coefficients(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+    
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+}
+
+pub struct Preprocessor {
+    filters: Vec<Biquad>,
+}
+
+impl Preprocessor {
+    pub fn new(settings: &PreprocessSettings, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f64;
+        let nyquist = sample_rate / 2.0;
+        let mut filters = Vec::new();
+        
+        if settings.high_pass_enabled && (settings.high_pass_hz as f64) < nyquist {
+            filters.push(Biquad::high_pass(sample_rate, settings.high_pass_hz as f64, std::f64::consts::FRAC_1_SQRT_2));
+        }
+        
+        if let Some(hum) = settings.hum_frequency.hz() {
+            for harmonic in 1..=settings.hum_harmonics {
+                let center = hum * harmonic as f64;
+                if center < nyquist {
+                    filters.push(Biquad::notch(sample_rate, center, HUM_NOTCH_Q));
+                }
+            }
+        }
+        
+        Self { filters }
+    }
+    
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.filters.iter_mut()
+            .fold(sample as f64, |signal, filter| filter.process(signal)) as f32
+    }
+}
+
+// False when the window's RMS level is under the gate threshold (treat it as silence)
+pub fn passes_noise_gate(samples: &[f32], settings: &PreprocessSettings) -> bool {
+    if !settings.noise_gate_enabled || samples.is_empty() {
+        return true;
+    }
+    
+    let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    let rms_db = 10.0 * mean_square.max(f32::MIN_POSITIVE).log10();
+    rms_db >= settings.noise_gate_db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SAMPLE_RATE: u32 = 44100;
+    
+    fn sine(frequency: f32, amplitude: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| amplitude * (std::f32::consts::TAU * frequency * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+    
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+    
+    // Output level of a sine at `frequency` relative to its input level, once the filters have
+    // settled
+    fn gain(settings: &PreprocessSettings, frequency: f32) -> f32 {
+        let input = sine(frequency, 0.5, 3.0);
+        let mut preprocessor = Preprocessor::new(settings, SAMPLE_RATE);
+        let output: Vec<f32> = input.iter().map(|&sample| preprocessor.process(sample)).collect();
+        let settled = SAMPLE_RATE as usize * 2;
+        rms(&output[settled..]) / rms(&input[settled..])
+    }
+    
+    fn hum_only(hum_frequency: HumFrequency) -> PreprocessSettings {
+        PreprocessSettings {
+            high_pass_enabled: false,
+            hum_frequency,
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn test_hum_notches_remove_mains_and_harmonics() {
+        for (hum_frequency, hum) in [(HumFrequency::Hz50, 50.0), (HumFrequency::Hz60, 60.0)] {
+            let settings = hum_only(hum_frequency);
+            for harmonic in 1..=settings.hum_harmonics {
+                let frequency = hum * harmonic as f32;
+                let gain = gain(&settings, frequency);
+                assert!(gain < 0.05, "{} Hz passed at {:.3}", frequency, gain);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_hum_notches_spare_strings_a_semitone_away() {
+        let semitone = 2.0_f32.powf(1.0 / 12.0);
+        for (hum_frequency, hum) in [(HumFrequency::Hz50, 50.0), (HumFrequency::Hz60, 60.0)] {
+            let settings = hum_only(hum_frequency);
+            for harmonic in 1..=settings.hum_harmonics {
+                for frequency in [hum * harmonic as f32 / semitone, hum * harmonic as f32 * semitone] {
+                    let gain = gain(&settings, frequency);
+                    assert!(gain > 0.95, "{:.1} Hz cut to {:.3}", frequency, gain);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn test_high_pass_keeps_low_strings_and_cuts_rumble() {
+        let settings = PreprocessSettings::default();
+        assert!(gain(&settings, 30.0) > 0.75);
+        assert!(gain(&settings, 55.0) > 0.95);
+        assert!(gain(&settings, 8.0) < 0.15);
+    }
+    
+    #[test]
+    fn test_noise_gate_threshold() {
+        let settings = PreprocessSettings {
+            noise_gate_enabled: true,
+            noise_gate_db: -60.0,
+            ..Default::default()
+        };
+        // A sine's RMS sits 3 dB under its peak: 0.001 is about -63 dB, 0.002 about -57 dB
+        assert!(!passes_noise_gate(&sine(220.0, 0.001, 0.1), &settings));
+        assert!(passes_noise_gate(&sine(220.0, 0.002, 0.1), &settings));
+        assert!(!passes_noise_gate(&[0.0; 1024], &settings));
+        
+        let disabled = PreprocessSettings { noise_gate_enabled: false, ..settings };
+        assert!(passes_noise_gate(&sine(220.0, 0.0001, 0.1), &disabled));
+    }
+}
@@ -1,8 +1,17 @@
 mod audio;
+mod filters;
+mod onset;
+mod pitch;
 mod scales;
+mod source;
+mod strobe;
+#[cfg(test)]
+mod synthetic;
 mod ui;
+mod wav;
 
 use eframe::egui;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 #[derive(Default)]
@@ -12,10 +21,20 @@ pub struct LyreTuneApp {
 }
 
 impl LyreTuneApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, file: Option<PathBuf>) -> Self {
         cc.egui_ctx.set_theme(egui::Theme::Dark);
         
-        let audio_state = Arc::new(Mutex::new(audio::AudioState::default()));
+        let mut ui_state = ui::UiState::restore(cc.storage);
+        if let Some(path) = file {
+            ui_state.open_recording(&path);
+        }
+        
+        // Start the audio thread with the saved device and analysis settings
+        let audio_state = Arc::new(Mutex::new(audio::AudioState {
+            settings: ui_state.analysis_settings,
+            input_device: ui_state.input_device.clone(),
+            ..Default::default()
+        }));
         let audio_state_clone = audio_state.clone();
         
         std::thread::spawn(move || {
@@ -24,7 +43,7 @@ impl LyreTuneApp {
         
         Self {
             audio_state,
-            ui_state: ui::UiState::default(),
+            ui_state,
         }
     }
 }
@@ -34,9 +53,26 @@ impl eframe::App for LyreTuneApp {
         ui::show(&mut self.ui_state, &self.audio_state, ctx);
         ctx.request_repaint_after(std::time::Duration::from_millis(50));
     }
+    
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.ui_state);
+    }
+}
+
+// `--file <path>` opens a WAV recording for analysis at startup
+fn file_argument() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--file" {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
 }
 
 fn main() -> eframe::Result<()> {
+    let file = file_argument();
+    
     let icon_data = include_bytes!("../icon.png");
     let icon = eframe::icon_data::from_png_bytes(icon_data).ok();
     
@@ -52,6 +88,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "LyreTune",
         native_options,
-        Box::new(|cc| Ok(Box::new(LyreTuneApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(LyreTuneApp::new(cc, file)))),
     )
 }
\ No newline at end of file
@@ -0,0 +1,211 @@
+// Pluck tracking. An energy-based onset detector marks the start of each pluck; frames analysed
+// during the attack are skipped and the rest are combined into one stabilized pitch per pluck,
+// so the display doesn't flicker with the transient or the slow drift of a decaying string.
+
+use crate::pitch::{PitchEstimate, MIN_CONFIDENCE};
+use std::collections::VecDeque;
+
+// Onsets are looked for in blocks of about this length
+const ONSET_BLOCK_SECONDS: f32 = 0.01;
+// A block this many times louder than the recent average starts a pluck (about 6 dB)
+const ONSET_ENERGY_RATIO: f32 = 4.0;
+// Quieter blocks never start a pluck, however sudden (mean square, about -60 dBFS)
+const MIN_ONSET_ENERGY: f32 = 1e-6;
+// Smoothing of the running block energy the ratio is measured against
+const ENERGY_SMOOTHING: f32 = 0.2;
+// Re-triggers within this time of an onset belong to the same pluck
+const MIN_ONSET_INTERVAL_SECONDS: f32 = 0.1;
+// Frames analysed this soon after an onset are part of the attack and ignored
+pub const ATTACK_SECONDS: f32 = 0.05;
+// The pluck's pitch is the median of this many of its latest frames, so it stays steady but
+// still follows a peg being turned while the string rings
+const PLUCK_MEDIAN_FRAMES: usize = 8;
+// This many unclear frames in a row mean the string has died away; a single one is more likely
+// a glitch (a knock, a neighbouring string) than the end of the note
+const PLUCK_END_FRAMES: usize = 3;
+
+pub struct OnsetDetector {
+    block_size: usize,
+    block_energy: f32,
+    block_pos: usize,
+    average_energy: f32,
+    min_interval_blocks: usize,
+    blocks_since_onset: usize,
+}
+
+impl OnsetDetector {
+    pub fn new(sample_rate: u32) -> Self {
+        let block_size = ((ONSET_BLOCK_SECONDS * sample_rate as f32) as usize).max(1);
+        let block_seconds = block_size as f32 / sample_rate as f32;
+        let min_interval_blocks = (MIN_ONSET_INTERVAL_SECONDS / block_seconds).ceil() as usize;
+        
+        Self {
+            block_size,
+            block_energy: 0.0,
+            block_pos: 0,
+            average_energy: 0.0,
+            min_interval_blocks,
+            blocks_since_onset: min_interval_blocks,
+        }
+    }
+    
+    // True when the block ending with this sample starts a new pluck
+    pub fn process(&mut self, sample: f32) -> bool {
+        self.block_energy += sample * sample;
+        self.block_pos += 1;
+        if self.block_pos < self.block_size {
+            return false;
+        }
+        
+        let energy = self.block_energy / self.block_size as f32;
+        self.block_energy = 0.0;
+        self.block_pos = 0;
+        self.blocks_since_onset = self.blocks_since_onset.saturating_add(1);
+        
+        let is_onset = energy > MIN_ONSET_ENERGY
+            && energy > self.average_energy * ONSET_ENERGY_RATIO
+            && self.blocks_since_onset >= self.min_interval_blocks;
+        if is_onset {
+            self.blocks_since_onset = 0;
+        }
+        
+        self.average_energy += ENERGY_SMOOTHING * (energy - self.average_energy);
+        is_onset
+    }
+}
+
+// The pitch of one pluck as it stands so far
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pluck {
+    // Counts up from 1 with every onset
+    pub id: u64,
+    // Median of the latest sustain frames; zero until the attack is over
+    pub frequency: f32,
+    pub confidence: f32,
+    // Sustain frames seen so far
+    pub frames: usize,
+    // False once the string has died away (or the frames stopped being confident)
+    pub ringing: bool,
+}
+
+#[derive(Default)]
+pub struct PluckTracker {
+    pluck: Option<Pluck>,
+    recent: VecDeque<PitchEstimate>,
+    sorted: Vec<f32>,
+    unclear_frames: usize,
+}
+
+impl PluckTracker {
+    pub fn start(&mut self) {
+        let id = self.pluck.map_or(1, |pluck| pluck.id + 1);
+        self.pluck = Some(Pluck {
+            id,
+            frequency: 0.0,
+            confidence: 0.0,
+            frames: 0,
+            ringing: true,
+        });
+        self.recent.clear();
+        self.unclear_frames = 0;
+    }
+    
+    // Adds a frame analysed after the attack
+    pub fn add_frame(&mut self, estimate: &PitchEstimate) {
+        let Some(pluck) = &mut self.pluck else {
+            return;
+        };
+        if !pluck.ringing {
+            return;
+        }
+        if estimate.confidence < MIN_CONFIDENCE {
+            // Before the first reading, unclear frames are just the pluck settling
+            if pluck.frames > 0 {
+                self.unclear_frames += 1;
+                pluck.ringing = self.unclear_frames < PLUCK_END_FRAMES;
+            }
+            return;
+        }
+        self.unclear_frames = 0;
+        
+        if self.recent.len() == PLUCK_MEDIAN_FRAMES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(*estimate);
+        
+        // The median shrugs off the odd octave error or frame caught between plucks
+        self.sorted.clear();
+        self.sorted.extend(self.recent.iter().map(|estimate| estimate.frequency));
+        self.sorted.sort_by(|a, b| a.total_cmp(b));
+        let middle = self.sorted.len() / 2;
+        pluck.frequency = if self.sorted.len().is_multiple_of(2) {
+            (self.sorted[middle - 1] + self.sorted[middle]) / 2.0
+        } else {
+            self.sorted[middle]
+        };
+        pluck.confidence = self.recent.iter().map(|estimate| estimate.confidence).sum::<f32>() / self.recent.len() as f32;
+        pluck.frames += 1;
+    }
+    
+    pub fn pluck(&self) -> Option<Pluck> {
+        self.pluck
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_onset_detected_once_per_burst() {
+        let sample_rate = 44100;
+        let mut detector = OnsetDetector::new(sample_rate);
+        let mut onsets = Vec::new();
+        
+        // Half a second of near silence, then a decaying tone starting at 0.5 s
+        for i in 0..sample_rate as usize {
+            let time = i as f32 / sample_rate as f32;
+            let sample = if time < 0.5 {
+                0.0001 * (i as f32 * 0.37).sin()
+            } else {
+                0.5 * (-(time - 0.5) * 3.0).exp() * (2.0 * std::f32::consts::PI * 220.0 * time).sin()
+            };
+            if detector.process(sample) {
+                onsets.push(time);
+            }
+        }
+        
+        assert_eq!(onsets.len(), 1, "onsets at {:?}", onsets);
+        assert!((onsets[0] - 0.5).abs() < 0.02);
+    }
+    
+    #[test]
+    fn test_pluck_frequency_is_median_of_sustain() {
+        let mut tracker = PluckTracker::default();
+        tracker.start();
+        for frequency in [220.0, 221.0, 440.0, 219.0, 220.5] {
+            tracker.add_frame(&PitchEstimate { frequency, confidence: 0.9 });
+        }
+        
+        let pluck = tracker.pluck().unwrap();
+        assert_eq!(pluck.id, 1);
+        assert_eq!(pluck.frames, 5);
+        assert_eq!(pluck.frequency, 220.5);
+        assert!(pluck.ringing);
+        
+        // A lone unclear frame doesn't end the pluck, a run of them does
+        let unclear = PitchEstimate { frequency: 0.0, confidence: 0.0 };
+        tracker.add_frame(&unclear);
+        tracker.add_frame(&PitchEstimate { frequency: 220.0, confidence: 0.9 });
+        assert!(tracker.pluck().unwrap().ringing);
+        assert_eq!(tracker.pluck().unwrap().frames, 6);
+        for _ in 0..PLUCK_END_FRAMES {
+            tracker.add_frame(&unclear);
+        }
+        assert!(!tracker.pluck().unwrap().ringing);
+        
+        tracker.start();
+        assert_eq!(tracker.pluck().unwrap().id, 2);
+        assert_eq!(tracker.pluck().unwrap().frames, 0);
+    }
+}
@@ -0,0 +1,683 @@
+// Pitch detection engines. Every engine receives the same analysis frame (raw samples plus the
+// windowed magnitude spectrum) and reports a frequency and confidence, so `AudioState` is filled
+// identically whichever one is selected.
+
+use serde::{Deserialize, Serialize};
+
+// Lowest and highest fundamentals we search for (covers bass lyres up to the top strings)
+const MIN_FUNDAMENTAL_HZ: f32 = 25.0;
+const MAX_FUNDAMENTAL_HZ: f32 = 2000.0;
+const NUM_HARMONICS: usize = 6;
+
+// Frames whose fundamental explains less of the spectrum than this are treated as ambiguous
+pub const MIN_CONFIDENCE: f32 = 0.3;
+
+// Polyphonic matching: how far either side of its target a string's peak is looked for (narrowed
+// further between close neighbours so a peak can only belong to one string), and how strong a
+// peak must be, against the loudest string and against the band's average level, to count
+const MAX_STRING_WINDOW_CENTS: f32 = 100.0;
+const MIN_STRING_LEVEL: f32 = 0.05;
+const MIN_STRING_PROMINENCE: f32 = 8.0;
+// Ratio to a lower string's peak within which a peak is taken to be that string's harmonic
+const HARMONIC_MATCH_CENTS: f32 = 30.0;
+
+// Inharmonicity fit: partials looked for, how far from the predicted position (in cents, but at
+// least two bins) each is searched for, and how strong it must be against the strongest partial
+const FIT_PARTIALS: usize = 12;
+const FIT_SEARCH_CENTS: f32 = 30.0;
+const MIN_FIT_PARTIAL_LEVEL: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PitchEngine {
+    Spectral,
+    Yin,
+}
+
+pub struct AnalysisFrame<'a> {
+    pub samples: &'a [f32],
+    pub spectrum: &'a [f32],
+    pub sample_rate: u32,
+    pub fft_size: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PitchEstimate {
+    pub frequency: f32,
+    pub confidence: f32,
+}
+
+// A string of the scale that is currently sounding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StringPitch {
+    pub frequency: f32,
+    // Deviation from the string's target
+    pub cents: f32,
+    // Peak magnitude relative to the loudest sounding string (0..1)
+    pub level: f32,
+}
+
+// Stiff-string model of the detected note: partial n sits at n * f0 * sqrt(1 + B n^2)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PartialFit {
+    pub inharmonicity: f32,
+    // Fitted frequency of the first partial, f0 * sqrt(1 + B)
+    pub fundamental: f32,
+    // Average of partial n / n weighted by each partial's strength, roughly the pitch heard
+    // when the upper partials are sharp
+    pub perceived: f32,
+    pub partials: usize,
+}
+
+pub trait PitchDetector: Send {
+    fn detect(&mut self, frame: &AnalysisFrame) -> PitchEstimate;
+    
+    // How many of the most recent samples of a frame the estimate depends on
+    fn span(&self, sample_rate: u32, fft_size: usize) -> usize;
+}
+
+pub fn create_detector(engine: PitchEngine) -> Box<dyn PitchDetector> {
+    match engine {
+        PitchEngine::Spectral => Box::new(SpectralDetector),
+        PitchEngine::Yin => Box::new(YinDetector::default()),
+    }
+}
+
+pub struct SpectralDetector;
+
+impl PitchDetector for SpectralDetector {
+    fn detect(&mut self, frame: &AnalysisFrame) -> PitchEstimate {
+        let (bin, confidence) = find_fundamental(frame.spectrum, frame.sample_rate, frame.fft_size);
+        PitchEstimate {
+            frequency: refine_fundamental(frame.spectrum, bin, frame.sample_rate, frame.fft_size),
+            confidence,
+        }
+    }
+    
+    fn span(&self, _sample_rate: u32, fft_size: usize) -> usize {
+        fft_size
+    }
+}
+
+// YIN (de Cheveigné & Kawahara, 2002). Works on the most recent few periods of the lowest
+// detectable note rather than the whole FFT window, which suits short plucked notes.
+pub struct YinDetector {
+    threshold: f32,
+    difference: Vec<f32>,
+}
+
+impl Default for YinDetector {
+    fn default() -> Self {
+        Self {
+            threshold: 0.15,
+            difference: Vec::new(),
+        }
+    }
+}
+
+// Longest period searched for. A small window at a high sample rate (4096 samples at 96 kHz)
+// can't hold two periods of the lowest note, so the lowest detectable note rises to what does fit.
+fn yin_max_tau(sample_rate: u32, window_len: usize) -> usize {
+    ((sample_rate as f32 / MIN_FUNDAMENTAL_HZ).ceil() as usize).min(window_len / 2)
+}
+
+impl PitchDetector for YinDetector {
+    fn detect(&mut self, frame: &AnalysisFrame) -> PitchEstimate {
+        let sample_rate = frame.sample_rate as f32;
+        let min_tau = ((sample_rate / MAX_FUNDAMENTAL_HZ) as usize).max(2);
+        let max_tau = yin_max_tau(frame.sample_rate, frame.samples.len());
+        if max_tau <= min_tau + 1 {
+            return PitchEstimate::default();
+        }
+        
+        // Integration window of one longest period, plus max_tau samples to compare against
+        let window = max_tau;
+        let samples = &frame.samples[frame.samples.len() - window - max_tau..];
+        
+        // Difference function
+        self.difference.clear();
+        self.difference.resize(max_tau + 1, 0.0);
+        for tau in 1..=max_tau {
+            let mut sum = 0.0;
+            for j in 0..window {
+                let delta = samples[j] - samples[j + tau];
+                sum += delta * delta;
+            }
+            self.difference[tau] = sum;
+        }
+        
+        // Cumulative mean normalized difference
+        self.difference[0] = 1.0;
+        let mut running_sum = 0.0;
+        for tau in 1..=max_tau {
+            running_sum += self.difference[tau];
+            self.difference[tau] = if running_sum > 0.0 {
+                self.difference[tau] * tau as f32 / running_sum
+            } else {
+                1.0
+            };
+        }
+        
+        // First dip below the threshold, at its lowest point over the whole stretch below the
+        // threshold: noise puts small bumps on the way down, and stopping at the first one
+        // reads a long period short. If nothing crosses, report the global minimum with zero
+        // confidence (the frame is aperiodic).
+        let (tau, periodic) = match (min_tau..max_tau).find(|&tau| self.difference[tau] < self.threshold) {
+            Some(start) => {
+                let end = (start..max_tau).find(|&tau| self.difference[tau] >= self.threshold).unwrap_or(max_tau);
+                let tau = (start..end)
+                    .min_by(|&a, &b| self.difference[a].total_cmp(&self.difference[b]))
+                    .unwrap_or(start);
+                (tau, true)
+            }
+            None => {
+                let tau = (min_tau..max_tau)
+                    .min_by(|&a, &b| self.difference[a].total_cmp(&self.difference[b]))
+                    .unwrap_or(min_tau);
+                (tau, false)
+            }
+        };
+        
+        // Parabolic interpolation of the dip for a sub-sample period
+        let (left, center, right) = (self.difference[tau - 1], self.difference[tau], self.difference[tau + 1]);
+        let denominator = left - 2.0 * center + right;
+        let offset = if denominator.abs() > f32::EPSILON {
+            (0.5 * (left - right) / denominator).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        
+        PitchEstimate {
+            frequency: sample_rate / (tau as f32 + offset),
+            confidence: if periodic { (1.0 - center).clamp(0.0, 1.0) } else { 0.0 },
+        }
+    }
+    
+    // The integration window plus the lags compared against it
+    fn span(&self, sample_rate: u32, fft_size: usize) -> usize {
+        2 * yin_max_tau(sample_rate, fft_size)
+    }
+}
+
+// Harmonic-sum fundamental estimator. Every candidate bin is scored by the energy at its
+// harmonics minus the energy half way between them, so an octave above the true pitch is
+// penalized by the odd partials it misses and an octave below scores only half the partials.
+// Returns the winning bin and a 0..1 confidence (share of the band energy it explains).
+fn find_fundamental(magnitudes: &[f32], sample_rate: u32, fft_size: usize) -> (usize, f32) {
+    let bin_width = sample_rate as f32 / fft_size as f32;
+    let min_bin = ((MIN_FUNDAMENTAL_HZ / bin_width).ceil() as usize).max(2);
+    let max_bin = ((MAX_FUNDAMENTAL_HZ / bin_width) as usize).min(magnitudes.len() / NUM_HARMONICS);
+    
+    if min_bin >= max_bin {
+        return (0, 0.0);
+    }
+    
+    let mut best_bin = 0;
+    let mut best_score = 0.0;
+    
+    for bin in min_bin..=max_bin {
+        let mut score = 0.0;
+        for harmonic in 1..=NUM_HARMONICS {
+            // Allow the partials to drift a little (inharmonicity, bin rounding of the candidate)
+            let radius = harmonic.div_ceil(2);
+            let weight = 1.0 / (harmonic as f32).sqrt();
+            let on_harmonic = local_max(magnitudes, bin * harmonic, radius);
+            let between = local_max(magnitudes, bin * harmonic - bin / 2, radius);
+            score += weight * (on_harmonic - 0.5 * between);
+        }
+        
+        if score > best_score {
+            best_score = score;
+            best_bin = bin;
+        }
+    }
+    
+    if best_bin == 0 {
+        return (0, 0.0);
+    }
+    
+    // Neighbouring candidates tie on the fundamental's own peak, so settle on its top bin
+    best_bin = (best_bin - 1..=best_bin + 1)
+        .max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))
+        .unwrap_or(best_bin);
+    
+    // Confidence: how much of the energy in the analysed band sits on the winner's harmonics
+    let band_end = (max_bin * NUM_HARMONICS).min(magnitudes.len());
+    let band_energy: f32 = magnitudes[min_bin..band_end].iter().map(|m| m * m).sum();
+    let harmonic_energy: f32 = (1..=NUM_HARMONICS)
+        .map(|harmonic| {
+            let center = best_bin * harmonic;
+            let radius = harmonic.div_ceil(2) + 1;
+            let start = center.saturating_sub(radius).max(min_bin);
+            let end = (center + radius + 1).min(band_end);
+            if start < end {
+                magnitudes[start..end].iter().map(|m| m * m).sum::<f32>()
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    
+    let confidence = if band_energy > 0.0 {
+        (harmonic_energy / band_energy).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    
+    (best_bin, confidence)
+}
+
+// Looks for every string of the scale at once: each target gets the strongest spectral peak
+// within its own window, so several ringing strings are reported from one frame. Fills
+// `readings` with one entry per target, `None` for strings that aren't sounding.
+pub fn match_strings(
+    magnitudes: &[f32],
+    sample_rate: u32,
+    fft_size: usize,
+    targets: &[f32],
+    readings: &mut Vec<Option<StringPitch>>,
+) {
+    readings.clear();
+    readings.resize(targets.len(), None);
+    
+    let bin_width = sample_rate as f32 / fft_size as f32;
+    let highest_target = targets.iter().cloned().fold(0.0, f32::max);
+    let band_start = ((MIN_FUNDAMENTAL_HZ / bin_width) as usize).max(1);
+    let band_end = ((highest_target * 1.1 / bin_width) as usize).min(magnitudes.len());
+    if band_start >= band_end {
+        return;
+    }
+    let average_level = magnitudes[band_start..band_end].iter().sum::<f32>() / (band_end - band_start) as f32;
+    
+    // (target index, refined frequency, peak magnitude) of every string with a peak in its window
+    let mut candidates: Vec<(usize, f32, f32)> = Vec::new();
+    
+    for (index, &target) in targets.iter().enumerate() {
+        if target <= 0.0 {
+            continue;
+        }
+        
+        let nearest_neighbour_cents = targets.iter()
+            .enumerate()
+            .filter(|&(other, &frequency)| other != index && frequency > 0.0)
+            .map(|(_, &frequency)| (1200.0 * (frequency / target).log2()).abs())
+            .filter(|&cents| cents > 0.0)
+            .fold(f32::MAX, f32::min);
+        let window_cents = MAX_STRING_WINDOW_CENTS.min(nearest_neighbour_cents / 2.0);
+        
+        let ratio = 2f32.powf(window_cents / 1200.0);
+        let low = ((target / ratio / bin_width).floor() as usize).max(1);
+        let high = ((target * ratio / bin_width).ceil() as usize).min(magnitudes.len() - 2);
+        if low >= high {
+            continue;
+        }
+        
+        let Some(peak) = (low..=high).max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b])) else {
+            continue;
+        };
+        let magnitude = magnitudes[peak];
+        
+        // A maximum on the window's edge is the skirt of a peak outside it, not a peak of its own
+        if peak == low || peak == high || magnitudes[peak - 1] > magnitude || magnitudes[peak + 1] > magnitude {
+            continue;
+        }
+        if magnitude < average_level * MIN_STRING_PROMINENCE {
+            continue;
+        }
+        
+        let offset = gaussian_peak_offset(magnitudes[peak - 1], magnitude, magnitudes[peak + 1]);
+        candidates.push((index, (peak as f32 + offset) * bin_width, magnitude));
+    }
+    
+    let loudest = candidates.iter().map(|&(_, _, magnitude)| magnitude).fold(0.0, f32::max);
+    candidates.retain(|&(_, _, magnitude)| magnitude >= loudest * MIN_STRING_LEVEL);
+    
+    // A lower string's 2nd, 3rd... partial can fall on another string's target (octaves, fifths).
+    // Such a peak is credited to the lower string unless it is the stronger of the two.
+    candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let mut accepted: Vec<(usize, f32, f32)> = Vec::new();
+    for (index, frequency, magnitude) in candidates {
+        let is_harmonic = accepted.iter().any(|&(_, lower_frequency, lower_magnitude)| {
+            let harmonic = (frequency / lower_frequency).round();
+            harmonic >= 2.0
+                && (1200.0 * (frequency / (lower_frequency * harmonic)).log2()).abs() < HARMONIC_MATCH_CENTS
+                && magnitude < lower_magnitude
+        });
+        if !is_harmonic {
+            accepted.push((index, frequency, magnitude));
+        }
+    }
+    
+    for (index, frequency, magnitude) in accepted {
+        readings[index] = Some(StringPitch {
+            frequency,
+            cents: 1200.0 * (frequency / targets[index]).log2(),
+            level: magnitude / loudest,
+        });
+    }
+}
+
+// Locates the partials of a note whose first partial is near `fundamental` and fits the
+// inharmonicity coefficient B by least squares on (f_n / n)^2 = f0^2 + f0^2 B n^2. Each partial
+// is searched for where the fit so far predicts it, so strongly stretched upper partials are
+// still found. Needs at least three partials.
+pub fn fit_inharmonicity(magnitudes: &[f32], sample_rate: u32, fft_size: usize, fundamental: f32) -> Option<PartialFit> {
+    if fundamental <= 0.0 {
+        return None;
+    }
+    
+    let bin_width = sample_rate as f32 / fft_size as f32;
+    let mut found: Vec<(f32, f32, f32)> = Vec::with_capacity(FIT_PARTIALS);
+    let mut f0 = fundamental;
+    let mut inharmonicity = 0.0;
+    
+    for harmonic in 1..=FIT_PARTIALS {
+        let n = harmonic as f32;
+        let predicted = n * f0 * (1.0 + inharmonicity * n * n).sqrt();
+        let radius = (predicted * (2f32.powf(FIT_SEARCH_CENTS / 1200.0) - 1.0) / bin_width).max(2.0);
+        let low = ((predicted / bin_width - radius).floor() as usize).max(1);
+        let high = ((predicted / bin_width + radius).ceil() as usize).min(magnitudes.len().saturating_sub(2));
+        if low >= high {
+            break;
+        }
+        
+        let Some(peak) = (low..=high).max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b])) else {
+            break;
+        };
+        let magnitude = magnitudes[peak];
+        if peak == low || peak == high || magnitudes[peak - 1] > magnitude || magnitudes[peak + 1] > magnitude {
+            continue;
+        }
+        
+        let offset = gaussian_peak_offset(magnitudes[peak - 1], magnitude, magnitudes[peak + 1]);
+        found.push((n, (peak as f32 + offset) * bin_width, magnitude));
+        
+        if let Some((fitted_f0, fitted_inharmonicity)) = fit_stiff_string(&found) {
+            f0 = fitted_f0;
+            inharmonicity = fitted_inharmonicity;
+        }
+    }
+    
+    // Drop peaks that are only noise next to the real partials, then fit once more
+    let strongest = found.iter().map(|&(_, _, magnitude)| magnitude).fold(0.0, f32::max);
+    found.retain(|&(_, _, magnitude)| magnitude >= strongest * MIN_FIT_PARTIAL_LEVEL);
+    if found.len() < 3 {
+        return None;
+    }
+    let (f0, inharmonicity) = fit_stiff_string(&found)?;
+    
+    let weight_total: f32 = found.iter().map(|&(_, _, magnitude)| magnitude).sum();
+    let perceived = found.iter()
+        .map(|&(n, frequency, magnitude)| magnitude * frequency / n)
+        .sum::<f32>() / weight_total;
+    
+    Some(PartialFit {
+        inharmonicity,
+        fundamental: f0 * (1.0 + inharmonicity).sqrt(),
+        perceived,
+        partials: found.len(),
+    })
+}
+
+// Magnitude-weighted linear regression of (f_n / n)^2 against n^2 over (n, f_n, magnitude)
+// triples, returning (f0, B). A single partial pins f0 with B taken as zero.
+fn fit_stiff_string(partials: &[(f32, f32, f32)]) -> Option<(f32, f32)> {
+    if partials.len() == 1 {
+        let (n, frequency, _) = partials[0];
+        return Some((frequency / n, 0.0));
+    }
+    
+    let points = partials.iter().map(|&(n, frequency, magnitude)| {
+        let per_partial = (frequency / n) as f64;
+        ((n * n) as f64, per_partial * per_partial, magnitude as f64)
+    });
+    let (mut w_sum, mut x_sum, mut y_sum, mut xx_sum, mut xy_sum) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (x, y, w) in points {
+        w_sum += w;
+        x_sum += w * x;
+        y_sum += w * y;
+        xx_sum += w * x * x;
+        xy_sum += w * x * y;
+    }
+    
+    let denominator = w_sum * xx_sum - x_sum * x_sum;
+    if w_sum <= 0.0 || denominator.abs() < f64::EPSILON {
+        return None;
+    }
+    let slope = (w_sum * xy_sum - x_sum * y_sum) / denominator;
+    let intercept = (y_sum - slope * x_sum) / w_sum;
+    if intercept <= 0.0 {
+        return None;
+    }
+    
+    Some((intercept.sqrt() as f32, (slope / intercept) as f32))
+}
+
+// Sub-bin refinement of the fundamental. Each resolved partial's peak is located with Gaussian
+// (log-parabolic) interpolation, divided back down by its harmonic number, and the estimates are
+// averaged with a bias towards the strong, low partials. This keeps readings cent-accurate on
+// low strings where a single bin spans 20+ cents, and still works when the fundamental is weak.
+fn refine_fundamental(magnitudes: &[f32], bin: usize, sample_rate: u32, fft_size: usize) -> f32 {
+    if bin == 0 {
+        return 0.0;
+    }
+    
+    let strongest = (1..=NUM_HARMONICS)
+        .map(|harmonic| local_max(magnitudes, bin * harmonic, harmonic.div_ceil(2)))
+        .fold(0.0, f32::max);
+    
+    let mut weighted_sum = 0.0;
+    let mut weight_total = 0.0;
+    
+    for harmonic in 1..=NUM_HARMONICS {
+        let radius = harmonic.div_ceil(2);
+        let Some(peak) = local_max_index(magnitudes, bin * harmonic, radius) else {
+            continue;
+        };
+        let magnitude = magnitudes[peak];
+        
+        // Skip partials buried in the noise floor or sitting on the edge of the search window
+        if magnitude < strongest * 0.1 || peak == 0 || peak + 1 >= magnitudes.len() {
+            continue;
+        }
+        if magnitudes[peak - 1] > magnitude || magnitudes[peak + 1] > magnitude {
+            continue;
+        }
+        
+        let offset = gaussian_peak_offset(magnitudes[peak - 1], magnitude, magnitudes[peak + 1]);
+        let partial_frequency = (peak as f32 + offset) * sample_rate as f32 / fft_size as f32;
+        let weight = magnitude / harmonic as f32;
+        
+        weighted_sum += weight * partial_frequency / harmonic as f32;
+        weight_total += weight;
+    }
+    
+    if weight_total > 0.0 {
+        weighted_sum / weight_total
+    } else {
+        index_to_frequency(bin, sample_rate, fft_size)
+    }
+}
+
+// Fractional offset (-0.5..0.5 bins) of a spectral peak from its three surrounding magnitudes,
+// fitting a parabola to the log spectrum (exact for a Gaussian-shaped main lobe)
+fn gaussian_peak_offset(left: f32, center: f32, right: f32) -> f32 {
+    if left <= 0.0 || center <= 0.0 || right <= 0.0 {
+        return 0.0;
+    }
+    
+    let (l, c, r) = (left.ln(), center.ln(), right.ln());
+    let denominator = l - 2.0 * c + r;
+    if denominator.abs() < f32::EPSILON {
+        return 0.0;
+    }
+    
+    (0.5 * (l - r) / denominator).clamp(-0.5, 0.5)
+}
+
+fn local_max_index(magnitudes: &[f32], center: usize, radius: usize) -> Option<usize> {
+    let start = center.saturating_sub(radius);
+    let end = (center + radius + 1).min(magnitudes.len());
+    (start..end).max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))
+}
+
+fn local_max(magnitudes: &[f32], center: usize, radius: usize) -> f32 {
+    let start = center.saturating_sub(radius);
+    let end = (center + radius + 1).min(magnitudes.len());
+    if start >= end {
+        return 0.0;
+    }
+    magnitudes[start..end].iter().cloned().fold(0.0, f32::max)
+}
+
+fn index_to_frequency(index: usize, sample_rate: u32, fft_size: usize) -> f32 {
+    index as f32 * sample_rate as f32 / (fft_size as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::cents_between;
+    use num_complex::Complex;
+    use rustfft::FftPlanner;
+    
+    const SAMPLE_RATE: u32 = 44100;
+    const FFT_SIZE: usize = 32768;
+    
+    // `(frequency, amplitude)` partials summed over one analysis window
+    fn tone(partials: &[(f32, f32)], sample_rate: u32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| {
+                let time = i as f64 / sample_rate as f64;
+                partials.iter()
+                    .map(|&(frequency, amplitude)| amplitude * (std::f64::consts::TAU * frequency as f64 * time).sin() as f32)
+                    .sum()
+            })
+            .collect()
+    }
+    
+    // Hann-windowed magnitude spectrum, as the analyzer hands it to the detectors
+    fn spectrum(partials: &[(f32, f32)]) -> Vec<f32> {
+        let samples = tone(partials, SAMPLE_RATE, FFT_SIZE);
+        let mut buffer: Vec<Complex<f32>> = samples.iter()
+            .enumerate()
+            .map(|(i, &sample)| {
+                let weight = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_SIZE as f32).cos();
+                Complex::new(sample * weight, 0.0)
+            })
+            .collect();
+        FftPlanner::new().plan_fft_forward(FFT_SIZE).process(&mut buffer);
+        buffer[..FFT_SIZE / 2].iter().map(|bin| bin.norm()).collect()
+    }
+    
+    // Harmonics falling off as 1/n
+    fn harmonics(frequency: f32, count: usize) -> Vec<(f32, f32)> {
+        (1..=count).map(|n| (frequency * n as f32, 1.0 / n as f32)).collect()
+    }
+    
+    fn bin_frequency(bin: usize) -> f32 {
+        index_to_frequency(bin, SAMPLE_RATE, FFT_SIZE)
+    }
+    
+    #[test]
+    fn test_find_fundamental_picks_the_fundamental() {
+        let magnitudes = spectrum(&harmonics(196.0, 8));
+        let (bin, confidence) = find_fundamental(&magnitudes, SAMPLE_RATE, FFT_SIZE);
+        assert!(cents_between(bin_frequency(bin), 196.0).abs() < 10.0, "picked {} Hz", bin_frequency(bin));
+        assert!(confidence > 0.5);
+    }
+    
+    #[test]
+    fn test_find_fundamental_with_a_weak_fundamental() {
+        // The 2nd and 3rd harmonics are much stronger, but there is no pitch an octave up
+        let mut partials = harmonics(110.0, 8);
+        partials[0].1 = 0.1;
+        let magnitudes = spectrum(&partials);
+        let (bin, _) = find_fundamental(&magnitudes, SAMPLE_RATE, FFT_SIZE);
+        assert!(cents_between(bin_frequency(bin), 110.0).abs() < 20.0, "picked {} Hz", bin_frequency(bin));
+    }
+    
+    #[test]
+    fn test_find_fundamental_of_silence() {
+        let (bin, confidence) = find_fundamental(&vec![0.0; FFT_SIZE / 2], SAMPLE_RATE, FFT_SIZE);
+        assert_eq!((bin, confidence), (0, 0.0));
+    }
+    
+    #[test]
+    fn test_refine_fundamental_between_bins() {
+        // A bin is 1.35 Hz wide, so these all fall somewhere between two bins
+        for frequency in [98.3, 196.0, 261.9, 440.7] {
+            let magnitudes = spectrum(&harmonics(frequency, 6));
+            let (bin, _) = find_fundamental(&magnitudes, SAMPLE_RATE, FFT_SIZE);
+            let refined = refine_fundamental(&magnitudes, bin, SAMPLE_RATE, FFT_SIZE);
+            assert!(cents_between(refined, frequency).abs() < 0.5, "{} Hz refined to {} Hz", frequency, refined);
+        }
+    }
+    
+    #[test]
+    fn test_match_strings_reads_each_sounding_string() {
+        let targets = [196.0, 220.0, 261.6];
+        let sharp = 261.6 * 2f32.powf(10.0 / 1200.0);
+        let mut partials = harmonics(196.0, 4);
+        partials.extend(harmonics(sharp, 4));
+        let magnitudes = spectrum(&partials);
+        
+        let mut readings = Vec::new();
+        match_strings(&magnitudes, SAMPLE_RATE, FFT_SIZE, &targets, &mut readings);
+        assert_eq!(readings.len(), 3);
+        let first = readings[0].expect("196 Hz string not found");
+        assert!(first.cents.abs() < 0.5);
+        assert!(readings[1].is_none());
+        let third = readings[2].expect("261.6 Hz string not found");
+        assert!((third.cents - 10.0).abs() < 0.5);
+    }
+    
+    #[test]
+    fn test_match_strings_credits_harmonics_to_the_lower_string() {
+        // The 110 Hz string's second harmonic sits right on the 220 Hz target
+        let magnitudes = spectrum(&harmonics(110.0, 6));
+        let mut readings = Vec::new();
+        match_strings(&magnitudes, SAMPLE_RATE, FFT_SIZE, &[110.0, 220.0], &mut readings);
+        assert!(readings[0].is_some());
+        assert!(readings[1].is_none());
+    }
+    
+    #[test]
+    fn test_fit_inharmonicity_recovers_the_stretch() {
+        let (f0, inharmonicity) = (110.0, 2e-4);
+        let partials: Vec<(f32, f32)> = (1..=10)
+            .map(|n| {
+                let n = n as f32;
+                (n * f0 * (1.0 + inharmonicity * n * n).sqrt(), 1.0 / n)
+            })
+            .collect();
+        let magnitudes = spectrum(&partials);
+        
+        let fit = fit_inharmonicity(&magnitudes, SAMPLE_RATE, FFT_SIZE, partials[0].0).expect("no fit");
+        assert!((fit.inharmonicity - inharmonicity).abs() < 0.1 * inharmonicity, "fitted B = {:e}", fit.inharmonicity);
+        assert!(cents_between(fit.fundamental, partials[0].0).abs() < 0.5);
+        assert!(fit.partials >= 8);
+        // Sharp partials make the note sound above its first partial
+        assert!(fit.perceived > fit.fundamental);
+    }
+    
+    #[test]
+    fn test_fit_inharmonicity_needs_partials() {
+        let magnitudes = spectrum(&[(220.0, 1.0)]);
+        assert!(fit_inharmonicity(&magnitudes, SAMPLE_RATE, FFT_SIZE, 220.0).is_none());
+    }
+    
+    #[test]
+    fn test_yin_fits_its_lags_to_a_short_window() {
+        // 4096 samples at 96 kHz hold two periods down to about 47 Hz, not the usual 25 Hz
+        let sample_rate = 96000;
+        let samples = tone(&[(220.0, 1.0)], sample_rate, 4096);
+        let estimate = YinDetector::default().detect(&AnalysisFrame {
+            samples: &samples,
+            spectrum: &[],
+            sample_rate,
+            fft_size: samples.len(),
+        });
+        assert!(cents_between(estimate.frequency, 220.0).abs() < 5.0, "detected {} Hz", estimate.frequency);
+        assert!(estimate.confidence > 0.5);
+    }
+}
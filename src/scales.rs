@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ScaleType {
     Modes,
     Genres,
@@ -8,7 +10,7 @@ pub enum ScaleType {
     Phorminx,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Mode {
     Mixolydios,
     Hypodorios,
@@ -19,27 +21,153 @@ pub enum Mode {
     Hypophrygios,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Genus {
     Diatonic,
     Chromatic,
     Enharmonic,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SavedTemperament")]
 pub enum Temperament {
+    Equal,
+    Just,
+    JustAncient,
+    // Fifths narrowed by the given fraction of a syntonic comma. Saved under another name, as
+    // plain `Meantone` is how quarter-comma was saved before the fraction could be chosen.
+    #[serde(rename = "MeantoneOf")]
+    Meantone { fraction: MeantoneFraction },
+    Pythagorean,
+    // `key` is the pitch class (semitones above C) the table's C is placed on, so a well
+    // temperament can favour any key regardless of the scale's first note
+    Well { temperament: WellTemperament, key: i32 },
+}
+
+// Every form a temperament has been saved in, so older settings still load
+#[derive(Deserialize)]
+enum SavedTemperament {
     Equal,
     Just,
     JustAncient,
     Meantone,
+    MeantoneOf { fraction: MeantoneFraction },
+    Pythagorean,
+    Well { temperament: WellTemperament, key: i32 },
+}
+
+impl From<SavedTemperament> for Temperament {
+    fn from(saved: SavedTemperament) -> Self {
+        match saved {
+            SavedTemperament::Equal => Temperament::Equal,
+            SavedTemperament::Just => Temperament::Just,
+            SavedTemperament::JustAncient => Temperament::JustAncient,
+            SavedTemperament::Meantone => Temperament::Meantone { fraction: MeantoneFraction::Quarter },
+            SavedTemperament::MeantoneOf { fraction } => Temperament::Meantone { fraction },
+            SavedTemperament::Pythagorean => Temperament::Pythagorean,
+            SavedTemperament::Well { temperament, key } => Temperament::Well { temperament, key },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MeantoneFraction {
+    Third,
+    Quarter,
+    Fifth,
+    Sixth,
+}
+
+impl MeantoneFraction {
+    fn of_comma(&self) -> f64 {
+        match self {
+            MeantoneFraction::Third => 1.0 / 3.0,
+            MeantoneFraction::Quarter => 1.0 / 4.0,
+            MeantoneFraction::Fifth => 1.0 / 5.0,
+            MeantoneFraction::Sixth => 1.0 / 6.0,
+        }
+    }
+}
+
+// Historical circulating temperaments: every key is usable, but the ones nearest the home key
+// have the purest thirds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WellTemperament {
+    Werckmeister,
+    Kirnberger,
+    Vallotti,
+    Young,
+}
+
+impl WellTemperament {
+    // Cents above C of each pitch class C, C#, D, ... B
+    fn cents(&self) -> &'static [f32; 12] {
+        match self {
+            // Werckmeister III: C-G-D-A and B-F# narrowed by a quarter of the Pythagorean comma
+            WellTemperament::Werckmeister => &[0.0, 90.225, 192.18, 294.135, 390.225, 498.045, 588.27, 696.09, 792.18, 888.27, 996.09, 1092.18],
+            // Kirnberger III: C-G-D-A-E narrowed by a quarter of the syntonic comma, so C-E is pure
+            WellTemperament::Kirnberger => &[0.0, 90.225, 193.157, 294.135, 386.314, 498.045, 590.224, 696.578, 792.18, 889.735, 996.09, 1088.269],
+            // Vallotti: F-C-G-D-A-E-B narrowed by a sixth of the Pythagorean comma
+            WellTemperament::Vallotti => &[0.0, 94.135, 196.09, 298.045, 392.18, 501.955, 592.18, 698.045, 796.09, 894.135, 1000.0, 1090.225],
+            // Young's second temperament: as Vallotti, a fifth higher (C-G-D-A-E-B-F# narrowed)
+            WellTemperament::Young => &[0.0, 90.225, 196.09, 294.135, 392.18, 498.045, 588.27, 698.045, 792.18, 894.135, 996.09, 1090.225],
+        }
+    }
 }
 
+// The pitch everything is tuned from: `note` in `octave` (C-based numbering, so A4 is the A above
+// middle C) sounds at `frequency`. Any temperament is scaled so this note lands exactly on it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReferencePitch {
+    pub note: String,
+    pub octave: i32,
+    pub frequency: f32,
+}
+
+impl ReferencePitch {
+    pub fn a4(frequency: f32) -> Self {
+        Self {
+            note: "A".to_string(),
+            octave: 4,
+            frequency,
+        }
+    }
+    
+    // Frequencies worth offering for the reference note: an octave either side of where it falls
+    // with A4 at 440 Hz
+    pub fn frequency_range(&self) -> RangeInclusive<f32> {
+        let nominal = 440.0 * 2.0_f32.powf(semitones_from_a4(&self.note, self.octave) / 12.0);
+        nominal / 2.0..=nominal * 2.0
+    }
+}
+
+impl Default for ReferencePitch {
+    fn default() -> Self {
+        Self::a4(440.0)
+    }
+}
+
+// Common choices of A4; any other reference can be entered directly
+pub const CONCERT_PITCH_PRESETS: [(f32, &str); 4] = [
+    (440.0, "Modern (A4 = 440 Hz)"),
+    (432.0, "A4 = 432 Hz"),
+    (415.0, "Baroque (A4 = 415 Hz)"),
+    (392.0, "Low pitch, as in many ancient reconstructions (A4 = 392 Hz)"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScaleData {
     pub notes: Vec<String>,
     pub frequencies: Vec<f32>,
+    // The tuning the frequencies come from, so any other note can be placed in it
+    pub temperament: Temperament,
+    pub a4_frequency: f32,
+    // The tonic's pitch class, as semitones from A
+    pub tonic: f32,
 }
 
 impl ScaleData {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         scale_type: ScaleType,
         mode: Option<Mode>,
@@ -48,15 +176,35 @@ impl ScaleData {
         num_strings: usize,
         temperament: Temperament,
         octave_offset: i32,
+        reference: &ReferencePitch,
+        tonic_string: usize,
     ) -> Self {
         let scale_notes = get_scale_notes(scale_type, mode, genus, first_note);
-        let (notes_with_octaves, frequencies) = calculate_frequencies_with_octaves(&scale_notes, temperament, octave_offset, num_strings, Some(scale_type));
+        
+        // Just, meantone and Pythagorean intervals are pure (or tempered) from the tonic: one of the
+        // strings, the first by default, or e.g. the mese
+        let tonic_index = tonic_string.min(num_strings.min(scale_notes.len()).saturating_sub(1));
+        let tonic = scale_notes.get(tonic_index).map_or(0.0, |note| semitones_from_a4(note, 4));
+        
+        // Frequencies scale with A4, so the A4 that puts the reference note on its frequency follows
+        // from where that note falls with A4 at 1 Hz
+        let a4_frequency = reference.frequency / note_to_frequency(&reference.note, temperament, reference.octave, 1.0, tonic);
+        let (notes_with_octaves, frequencies) = calculate_frequencies_with_octaves(&scale_notes, temperament, octave_offset, num_strings, a4_frequency, tonic, Some(scale_type));
         
         Self {
             notes: notes_with_octaves,
             frequencies,
+            temperament,
+            a4_frequency,
+            tonic,
         }
     }
+    
+    // Frequency of any note in this tuning, e.g. to move the reference pitch onto another note
+    // without retuning the strings
+    pub fn frequency_of(&self, note: &str, octave: i32) -> f32 {
+        note_to_frequency(note, self.temperament, octave, self.a4_frequency, self.tonic)
+    }
 }
 
 fn get_scale_notes(
@@ -218,14 +366,16 @@ fn get_phorminx_scale(first_note: &str) -> Vec<String> {
     transpose_scale(&base_scale, "A", first_note)
 }
 
+#[allow(clippy::if_same_then_else, clippy::useless_conversion)]
 fn calculate_frequencies_with_octaves(
     notes: &[String],
     temperament: Temperament,
     octave_offset: i32,
     num_strings: usize,
+    a4_freq: f32,
+    tonic: f32,
     _scale_type_hint: Option<ScaleType>,
 ) -> (Vec<String>, Vec<f32>) {
-    let a4_freq = 440.0;
     let mut notes_with_octaves = Vec::new();
     let mut frequencies = Vec::new();
     let mut current_octave = octave_offset + 4;  // Base octave for reasonable frequency range
@@ -267,13 +417,13 @@ fn calculate_frequencies_with_octaves(
         let octave_for_freq = current_octave;
         
         
-        let freq = note_to_frequency(note, temperament, octave_for_freq, a4_freq);
+        let freq = note_to_frequency(note, temperament, octave_for_freq, a4_freq, tonic);
         
         // For display, use the same octave as frequency calculation
         let octave_for_display = octave_for_freq;
         
         // Calculate display octave based on C-based octave numbering
-        // where C4 is middle C and A4 = 440 Hz
+        // where C4 is middle C
         let display_octave = if note_base >= 'C' {
             octave_for_display
         } else {
@@ -308,7 +458,9 @@ fn calculate_frequencies_with_octaves(
 }
 
 
-fn note_to_frequency(note: &str, temperament: Temperament, octave: i32, a4_freq: f32) -> f32 {
+// Semitones from A4 to `note` in `octave`, in equal temperament; quarter-tone notes (`*`) land
+// halfway between semitones
+fn semitones_from_a4(note: &str, octave: i32) -> f32 {
     let semitones_from_a = match note.chars().next().unwrap_or('A') {
         'C' => -9,
         'D' => -7,
@@ -331,51 +483,93 @@ fn note_to_frequency(note: &str, temperament: Temperament, octave: i32, a4_freq:
         adjustment += 0.5; // Quarter tone (half semitone)
     }
     
-    // Calculate total semitones from A4 (440 Hz reference)
-    let total_semitones = semitones_from_a as f32 + adjustment + ((octave - 4) * 12) as f32;
+    // Calculate total semitones from A4
+    semitones_from_a as f32 + adjustment + ((octave - 4) * 12) as f32
+}
+
+// `tonic` is the tonic's pitch class, as semitones from A
+fn note_to_frequency(note: &str, temperament: Temperament, octave: i32, a4_freq: f32, tonic: f32) -> f32 {
+    let total_semitones = semitones_from_a4(note, octave);
     
     // Debug all note calculations
     // println!("DEBUG: {} note calculation - semitones_from_a={}, octave={}, total_semitones={}", 
     //          note, semitones_from_a, octave, total_semitones);
     
     match temperament {
-        Temperament::Equal => a4_freq * 2.0_f32.powf(total_semitones as f32 / 12.0),
-        Temperament::Just => a4_freq * get_just_ratio(total_semitones),
-        Temperament::JustAncient => a4_freq * get_just_ancient_ratio(total_semitones),
-        Temperament::Meantone => a4_freq * get_meantone_ratio(total_semitones),
+        Temperament::Equal => a4_freq * 2.0_f32.powf(total_semitones / 12.0),
+        Temperament::Just => a4_freq * get_just_ratio(total_semitones, tonic),
+        Temperament::JustAncient => a4_freq * get_just_ancient_ratio(total_semitones, tonic),
+        Temperament::Meantone { fraction } => {
+            let cents = tonic_relative_cents(total_semitones, tonic, &meantone_cents(fraction));
+            a4_freq * 2.0_f32.powf(cents / 1200.0)
+        }
+        Temperament::Pythagorean => {
+            let cents = tonic_relative_cents(total_semitones, tonic, &pythagorean_cents());
+            a4_freq * 2.0_f32.powf(cents / 1200.0)
+        }
+        Temperament::Well { temperament, key } => {
+            // C is 9 semitones below A
+            let cents = tonic_relative_cents(total_semitones, (key - 9) as f32, temperament.cents());
+            a4_freq * 2.0_f32.powf(cents / 1200.0)
+        }
     }
 }
 
-fn get_just_ratio(semitones: f32) -> f32 {
-    // For fractional semitones, use equal temperament approximation
-    // This is a simplified approach for quarter tones
-    if semitones.fract() != 0.0 {
-        return 2.0_f32.powf(semitones / 12.0);
-    }
+// Cents above A4 of a note `semitones` (equal-tempered) from A4, in a tuning that gives each of
+// the twelve semitone steps above the tonic its own size in cents. The tonic sits where equal
+// temperament puts it, and a quarter-tone step lies halfway (in cents) between its neighbours,
+// which splits the semitone into two equal dieses as in the enharmonic genus.
+fn tonic_relative_cents(semitones: f32, tonic: f32, step_cents: &[f32; 12]) -> f32 {
+    let cents_at = |step: f32| {
+        let octaves = (step / 12.0).floor();
+        let index = (step - octaves * 12.0).round() as usize % 12;
+        1200.0 * octaves + step_cents[index]
+    };
     
-    let ratios = [
-        1.0,      // A
-        16.0/15.0, // A#/Bb
-        9.0/8.0,   // B
-        6.0/5.0,   // C
-        5.0/4.0,   // C#/Db
-        4.0/3.0,   // D
-        45.0/32.0, // D#/Eb
-        3.0/2.0,   // E
-        8.0/5.0,   // F
-        5.0/3.0,   // F#/Gb
-        9.0/5.0,   // G
-        15.0/8.0,  // G#/Ab
+    let from_tonic = semitones - tonic;
+    let lower = from_tonic.floor();
+    let fraction = from_tonic - lower;
+    let cents = if fraction == 0.0 {
+        cents_at(lower)
+    } else {
+        cents_at(lower) + fraction * (cents_at(lower + 1.0) - cents_at(lower))
+    };
+    100.0 * tonic + cents
+}
+
+// Every step reached by stacking fifths up or down from the tonic, folded into one octave: six
+// fifths up (to the augmented fourth) and five down (to the minor second)
+fn chain_of_fifths_cents(fifth_cents: f64) -> [f32; 12] {
+    const FIFTHS_FOR_STEP: [i32; 12] = [0, -5, 2, -3, 4, -1, 6, 1, -4, 3, -2, 5];
+    FIFTHS_FOR_STEP.map(|fifths| (fifths as f64 * fifth_cents).rem_euclid(1200.0) as f32)
+}
+
+// Pure 3:2 fifths
+fn pythagorean_cents() -> [f32; 12] {
+    chain_of_fifths_cents(1200.0 * 1.5_f64.log2())
+}
+
+fn get_just_ratio(semitones: f32, tonic: f32) -> f32 {
+    let ratios: [f32; 12] = [
+        1.0,       // unison
+        16.0/15.0, // minor second
+        9.0/8.0,   // major second
+        6.0/5.0,   // minor third
+        5.0/4.0,   // major third
+        4.0/3.0,   // fourth
+        45.0/32.0, // augmented fourth
+        3.0/2.0,   // fifth
+        8.0/5.0,   // minor sixth
+        5.0/3.0,   // major sixth
+        9.0/5.0,   // minor seventh
+        15.0/8.0,  // major seventh
     ];
     
-    let octaves = (semitones / 12.0).floor() as i32;
-    let note_index = ((semitones % 12.0 + 12.0) % 12.0) as usize;
-    
-    
-    ratios[note_index] * 2.0_f32.powi(octaves)
+    let cents = tonic_relative_cents(semitones, tonic, &ratios.map(|ratio| 1200.0 * ratio.log2()));
+    2.0_f32.powf(cents / 1200.0)
 }
 
-fn get_just_ancient_ratio(semitones: f32) -> f32 {
+fn get_just_ancient_ratio(semitones: f32, tonic: f32) -> f32 {
     // 22-Shruti system based on ancient Greek/Indian musical theory
     // These are the cent values from the HTML implementation
     let shruti_cents = [
@@ -390,46 +584,25 @@ fn get_just_ancient_ratio(semitones: f32) -> f32 {
         ratios.push(2.0_f32.powf(cents / 1200.0));
     }
     
-    // In the HTML, the array is indexed by quarter-tones from A
-    // A=0, A*=1, A#=2, A#*=3, B=4, B*=5, C=6, C*=7, C#=8, C#*=9, D=10, D*=11, D#=12, D#*=13, E=14, E*=15, F=16, F*=17, F#=18, F#*=19, G=20, G*=21, G#=22, G#*=23
-    // For non-quarter-tone notes, we use the base note index (multiply semitones by 2)
+    // The array is indexed by quarter-tones above the tonic: with the tonic on A, A=0, A*=1,
+    // A#=2, A#*=3, B=4 and so on. For non-quarter-tone notes, we use the base note index
+    // (multiply semitones by 2)
+    let from_tonic = semitones - tonic;
+    let octaves = (from_tonic / 12.0).floor() as i32;
+    let semitone_in_octave = from_tonic - 12.0 * octaves as f32;
     
-    let octaves = (semitones / 12.0).floor() as i32;
-    let semitone_in_octave = (semitones % 12.0 + 12.0) % 12.0;
-    
-    // Convert semitones to quarter-tones from A
+    // Convert semitones to quarter-tones from the tonic
     // Each semitone = 2 quarter-tones, so multiply by 2
-    let quarter_tones_from_a = (semitone_in_octave * 2.0).round() as i32;
-    let quarter_tone_index = (quarter_tones_from_a % 24) as usize;
+    let quarter_tones = (semitone_in_octave * 2.0).round() as usize;
+    let octaves = octaves + (quarter_tones / 24) as i32;
     
-    ratios[quarter_tone_index] * 2.0_f32.powi(octaves)
+    2.0_f32.powf(tonic / 12.0) * ratios[quarter_tones % 24] * 2.0_f32.powi(octaves)
 }
 
-fn get_meantone_ratio(semitones: f32) -> f32 {
-    // For fractional semitones, use equal temperament approximation
-    if semitones.fract() != 0.0 {
-        return 2.0_f32.powf(semitones / 12.0);
-    }
-    
-    let ratios = [
-        1.0,        // A
-        1.0449,     // A#/Bb
-        1.1180,     // B
-        1.1963,     // C
-        1.2500,     // C#/Db
-        1.3375,     // D
-        1.3975,     // D#/Eb
-        1.4953,     // E
-        1.5625,     // F
-        1.6719,     // F#/Gb
-        1.7889,     // G
-        1.8692,     // G#/Ab
-    ];
-    
-    let octaves = (semitones / 12.0).floor() as i32;
-    let note_index = ((semitones % 12.0 + 12.0) % 12.0) as usize;
-    
-    ratios[note_index] * 2.0_f32.powi(octaves)
+// Fifths narrowed from a pure 3:2 by the given fraction of the syntonic comma (81:80), so
+// quarter-comma gives pure major thirds and third-comma pure minor thirds
+fn meantone_cents(fraction: MeantoneFraction) -> [f32; 12] {
+    chain_of_fifths_cents(1200.0 * (1.5_f64.log2() - fraction.of_comma() * (81.0_f64 / 80.0).log2()))
 }
 
 
@@ -513,8 +686,15 @@ fn transpose_scale(scale: &[&str], from_note: &str, to_note: &str) -> Vec<String
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::synthetic::cents_between;
+
+    // A mode of `num_strings` strings from `first_note`, the common case in the temperament tests
+    fn mode_scale(mode: Mode, first_note: &str, num_strings: usize, temperament: Temperament, reference: &ReferencePitch, tonic_string: usize) -> ScaleData {
+        ScaleData::new(ScaleType::Modes, Some(mode), None, first_note, num_strings, temperament, 0, reference, tonic_string)
+    }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_debug_scale_sequence() {
         // Test the actual scale sequence for A first note
         let scale_notes = get_scale_notes(ScaleType::Modes, Some(Mode::Dorios), None, "A");
@@ -553,6 +733,8 @@ mod tests {
                 7,
                 Temperament::Just,
                 0,
+                &ReferencePitch::default(),
+                0,
             );
             
             let actual_first_note = &scale_data.notes[0];
@@ -591,6 +773,8 @@ mod tests {
                 7,
                 Temperament::Just,
                 0,
+                &ReferencePitch::default(),
+                0,
             );
             
             let actual_first_note = &scale_data.notes[0];
@@ -615,6 +799,8 @@ mod tests {
                 7,
                 Temperament::Just,
                 0,
+                &ReferencePitch::default(),
+                0,
             );
             
             let actual_first_note = &scale_data.notes[0];
@@ -653,6 +839,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("Enharmonic genus with D first note:");
@@ -686,6 +874,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         // Debug the base scale first
@@ -718,6 +908,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         // Debug what notes we get from the scale
@@ -751,6 +943,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("First note A# test:");
@@ -767,6 +961,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("\nFirst note A* test:");
@@ -783,6 +979,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::collapsible_if)]
     fn test_first_note_issues() {
         // Test with "A" as first note to reproduce the ordering issue
         let scale_data_a = ScaleData::new(
@@ -793,6 +990,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("First note A test:");
@@ -810,6 +1009,8 @@ mod tests {
                 7,
                 Temperament::Just,
                 0,
+                &ReferencePitch::default(),
+                0,
             );
             
             println!("\nFirst note {} test:", first_note);
@@ -850,6 +1051,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_vec, clippy::unused_enumerate_index, clippy::manual_range_contains)]
     fn test_default_settings_comprehensive() {
         // Test the exact default settings: 7 strings, E first note, Dorios mode, octave 0
         let scale_data = ScaleData::new(
@@ -860,6 +1062,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("Default settings test:");
@@ -927,6 +1131,8 @@ mod tests {
             4,
             Temperament::Equal,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("Phorminx tuning with A first note:");
@@ -950,6 +1156,8 @@ mod tests {
             4,
             Temperament::Equal,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("\nPhorminx tuning with C first note:");
@@ -977,6 +1185,8 @@ mod tests {
             7,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         // Also test with more strings to see extended range
@@ -988,6 +1198,8 @@ mod tests {
             12,
             Temperament::Just,
             0,
+            &ReferencePitch::default(),
+            0,
         );
         
         // Test with lower octave offset to see lower range
@@ -999,6 +1211,8 @@ mod tests {
             12,
             Temperament::Just,
             -1,  // Lower octave
+            &ReferencePitch::default(),
+            0,
         );
         
         println!("Notes and frequencies (7 strings):");
@@ -1127,4 +1341,238 @@ mod tests {
             println!("Could not find B3 or D4 in notes: {:?}", scale_data.notes);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reference_pitch_applies_to_every_temperament() {
+        for temperament in [Temperament::Equal, Temperament::Just, Temperament::JustAncient, Temperament::Meantone { fraction: MeantoneFraction::Fifth }, Temperament::Pythagorean, Temperament::Well { temperament: WellTemperament::Vallotti, key: 7 }] {
+            let modern = mode_scale(Mode::Hypodorios, "A", 7, temperament, &ReferencePitch::default(), 0);
+            let baroque = mode_scale(Mode::Hypodorios, "A", 7, temperament, &ReferencePitch::a4(415.0), 0);
+            
+            assert_eq!(baroque.notes[0], "A4");
+            assert!((baroque.frequencies[0] - 415.0).abs() < 1e-3, "{:?}: {}", temperament, baroque.frequencies[0]);
+            for (modern_freq, baroque_freq) in modern.frequencies.iter().zip(&baroque.frequencies) {
+                assert!((baroque_freq / modern_freq - 415.0 / 440.0).abs() < 1e-5);
+            }
+            
+            // Any note can be the reference, here C4 = 256 Hz ("scientific pitch")
+            let scientific_pitch = ReferencePitch {
+                note: "C".to_string(),
+                octave: 4,
+                frequency: 256.0,
+            };
+            let scientific = mode_scale(Mode::Hypodorios, "A", 7, temperament, &scientific_pitch, 0);
+            let c5 = scientific.notes.iter().position(|note| note == "C5").unwrap();
+            assert!((scientific.frequencies[c5] - 512.0).abs() < 1e-2, "{:?}: {}", temperament, scientific.frequencies[c5]);
+        }
+    }
+
+    #[test]
+    fn test_reference_frequency_range_follows_the_note() {
+        let range_for = |note: &str, octave| ReferencePitch { note: note.to_string(), octave, frequency: 0.0 }.frequency_range();
+        assert!(ReferencePitch::default().frequency_range().contains(&392.0));
+        assert!(range_for("C", 2).contains(&65.4));
+        assert!(!range_for("C", 2).contains(&440.0));
+        assert!(range_for("A", 7).contains(&3520.0));
+    }
+
+    #[test]
+    fn test_moving_the_reference_note_keeps_the_tuning() {
+        let meantone = Temperament::Meantone { fraction: MeantoneFraction::Quarter };
+        for temperament in [Temperament::Equal, Temperament::Just, meantone, Temperament::Pythagorean] {
+            let before = mode_scale(Mode::Dorios, "E", 8, temperament, &ReferencePitch::a4(415.0), 0);
+            let reference = ReferencePitch {
+                note: "C".to_string(),
+                octave: 4,
+                frequency: before.frequency_of("C", 4),
+            };
+            let after = mode_scale(Mode::Dorios, "E", 8, temperament, &reference, 0);
+            for (a, b) in before.frequencies.iter().zip(&after.frequencies) {
+                assert!((a - b).abs() < 1e-3, "{:?}: {} became {}", temperament, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_saved_temperaments_still_load() {
+        let meantone: Temperament = serde_json::from_str("\"Meantone\"").unwrap();
+        assert_eq!(meantone, Temperament::Meantone { fraction: MeantoneFraction::Quarter });
+        
+        let sixth = Temperament::Meantone { fraction: MeantoneFraction::Sixth };
+        let saved = serde_json::to_string(&sixth).unwrap();
+        assert_eq!(serde_json::from_str::<Temperament>(&saved).unwrap(), sixth);
+        let well = Temperament::Well { temperament: WellTemperament::Vallotti, key: 2 };
+        assert_eq!(serde_json::from_str::<Temperament>(&serde_json::to_string(&well).unwrap()).unwrap(), well);
+    }
+
+    #[test]
+    fn test_pythagorean_ratios_from_first_note() {
+        // Dorios on E: E F G A B C D E, every step a whole number of pure fifths from E
+        let scale_data = ScaleData::new(
+            ScaleType::Modes,
+            Some(Mode::Dorios),
+            None,
+            "E",
+            8,
+            Temperament::Pythagorean,
+            0,
+            &ReferencePitch::default(),
+            0,
+        );
+        let expected_ratios = [1.0, 256.0 / 243.0, 32.0 / 27.0, 4.0 / 3.0, 3.0 / 2.0, 128.0 / 81.0, 16.0 / 9.0, 2.0];
+        for (i, expected) in expected_ratios.iter().enumerate() {
+            let ratio = scale_data.frequencies[i] / scale_data.frequencies[0];
+            assert!((ratio - expected).abs() < 1e-4, "{}: ratio {} expected {}", scale_data.notes[i], ratio, expected);
+        }
+        
+        // Anchored on the reference, so A (a pure fourth above E) is exactly 440 Hz
+        assert_eq!(scale_data.notes[3], "A4");
+        assert!((scale_data.frequencies[3] - 440.0).abs() < 1e-3);
+        
+        // The same intervals from any first note, including quarter-tone ones
+        for first_note in ["C", "C#", "D*", "F#", "A*", "B"] {
+            let transposed = ScaleData::new(
+                ScaleType::Modes,
+                Some(Mode::Dorios),
+                None,
+                first_note,
+                8,
+                Temperament::Pythagorean,
+                0,
+                &ReferencePitch::default(),
+                0,
+            );
+            for (i, expected) in expected_ratios.iter().enumerate() {
+                let ratio = transposed.frequencies[i] / transposed.frequencies[0];
+                assert!((ratio - expected).abs() < 1e-4, "{} from {}: ratio {} expected {}", transposed.notes[i], first_note, ratio, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pythagorean_enharmonic_quarter_tones() {
+        // Enharmonic tetrachord on C: C C* C# F, the limma C-C# split into two equal dieses and a
+        // pure ditone (81:64) up to the fourth
+        let scale_data = ScaleData::new(
+            ScaleType::Genres,
+            None,
+            Some(Genus::Enharmonic),
+            "C",
+            4,
+            Temperament::Pythagorean,
+            0,
+            &ReferencePitch::default(),
+            0,
+        );
+        assert_eq!(scale_data.notes, ["C4", "C*4", "C#4", "F4"]);
+        
+        let limma = cents_between(256.0, 243.0);
+        let diesis = cents_between(scale_data.frequencies[1], scale_data.frequencies[0]);
+        assert!((diesis - limma / 2.0).abs() < 0.01, "diesis {} cents", diesis);
+        assert!((cents_between(scale_data.frequencies[2], scale_data.frequencies[1]) - limma / 2.0).abs() < 0.01);
+        assert!((scale_data.frequencies[3] / scale_data.frequencies[2] - 81.0 / 64.0).abs() < 1e-4);
+        assert!((scale_data.frequencies[3] / scale_data.frequencies[0] - 4.0 / 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_well_temperaments_follow_their_tables() {
+        // Pitch classes of C D E F G A B C
+        let steps = [0, 2, 4, 5, 7, 9, 11, 12];
+        
+        for well in [WellTemperament::Werckmeister, WellTemperament::Kirnberger, WellTemperament::Vallotti, WellTemperament::Young] {
+            let table = well.cents();
+            let scale_data = mode_scale(Mode::Lydios, "C", 8, Temperament::Well { temperament: well, key: 0 }, &ReferencePitch::default(), 0);
+            for (i, &step) in steps.iter().enumerate() {
+                let expected = if step == 12 { 1200.0 } else { table[step] };
+                let cents = cents_between(scale_data.frequencies[i], scale_data.frequencies[0]);
+                assert!((cents - expected).abs() < 0.01, "{:?} {}: {} cents, expected {}", well, scale_data.notes[i], cents, expected);
+            }
+        }
+        
+        // Kirnberger III has a pure major third on its key, wherever the scale starts
+        let third = |key, first_note| {
+            let kirnberger = Temperament::Well { temperament: WellTemperament::Kirnberger, key };
+            let scale_data = mode_scale(Mode::Lydios, first_note, 3, kirnberger, &ReferencePitch::default(), 0);
+            cents_between(scale_data.frequencies[2], scale_data.frequencies[0])
+        };
+        let pure_third = cents_between(5.0, 4.0);
+        assert!((third(0, "C") - pure_third).abs() < 0.01);
+        assert!((third(7, "G") - pure_third).abs() < 0.01);
+        // With the key left on C, the third on G is wider
+        assert!(third(0, "G") > pure_third + 5.0);
+    }
+
+    #[test]
+    fn test_meantone_fifths_and_thirds() {
+        let comma = cents_between(81.0, 80.0);
+        
+        for (fraction, of_comma) in [
+            (MeantoneFraction::Third, 1.0 / 3.0),
+            (MeantoneFraction::Quarter, 1.0 / 4.0),
+            (MeantoneFraction::Fifth, 1.0 / 5.0),
+            (MeantoneFraction::Sixth, 1.0 / 6.0),
+        ] {
+            // A B C D E F G A
+            let scale_data = mode_scale(Mode::Hypodorios, "A", 8, Temperament::Meantone { fraction }, &ReferencePitch::default(), 0);
+            let fifth = cents_between(scale_data.frequencies[4], scale_data.frequencies[0]);
+            let expected = cents_between(3.0, 2.0) - of_comma * comma;
+            assert!((fifth - expected).abs() < 0.01, "{:?}: fifth {} cents, expected {}", fraction, fifth, expected);
+            assert!((scale_data.frequencies[7] / scale_data.frequencies[0] - 2.0).abs() < 1e-5);
+        }
+        
+        // Quarter-comma has pure major thirds (A-C#), third-comma pure minor thirds (A-C)
+        let quarter = mode_scale(Mode::Lydios, "A", 3, Temperament::Meantone { fraction: MeantoneFraction::Quarter }, &ReferencePitch::default(), 0);
+        assert!((quarter.frequencies[2] / quarter.frequencies[0] - 5.0 / 4.0).abs() < 1e-4);
+        let third = mode_scale(Mode::Hypodorios, "A", 3, Temperament::Meantone { fraction: MeantoneFraction::Third }, &ReferencePitch::default(), 0);
+        assert!((third.frequencies[2] / third.frequencies[0] - 6.0 / 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_meantone_quarter_tones_split_the_semitone() {
+        let scale_data = ScaleData::new(
+            ScaleType::Genres,
+            None,
+            Some(Genus::Enharmonic),
+            "C",
+            3,
+            Temperament::Meantone { fraction: MeantoneFraction::Quarter },
+            0,
+            &ReferencePitch::default(),
+            0,
+        );
+        assert_eq!(scale_data.notes, ["C4", "C*4", "C#4"]);
+        
+        let lower = cents_between(scale_data.frequencies[1], scale_data.frequencies[0]);
+        let upper = cents_between(scale_data.frequencies[2], scale_data.frequencies[1]);
+        assert!((lower - upper).abs() < 0.01, "{} and {} cents", lower, upper);
+        // Together they make the meantone diatonic semitone, three octaves less five tempered fifths
+        let fifth = cents_between(3.0, 2.0) - cents_between(81.0, 80.0) / 4.0;
+        assert!((lower + upper - (3600.0 - 5.0 * fifth)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_just_and_meantone_are_relative_to_the_tonic() {
+        // E F G A B C D E in just intonation from E, not A-major ratios
+        let just = mode_scale(Mode::Dorios, "E", 8, Temperament::Just, &ReferencePitch::default(), 0);
+        let expected_ratios = [1.0, 16.0 / 15.0, 6.0 / 5.0, 4.0 / 3.0, 3.0 / 2.0, 8.0 / 5.0, 9.0 / 5.0, 2.0];
+        for (i, expected) in expected_ratios.iter().enumerate() {
+            let ratio = just.frequencies[i] / just.frequencies[0];
+            assert!((ratio - expected).abs() < 1e-4, "{}: ratio {} expected {}", just.notes[i], ratio, expected);
+        }
+        
+        // Built on the mese (A) instead, E is a pure fourth below it and B a 9:8 tone above
+        let on_mese = mode_scale(Mode::Dorios, "E", 8, Temperament::Just, &ReferencePitch::default(), 3);
+        assert_eq!(on_mese.notes[3], "A4");
+        assert!((on_mese.frequencies[3] / on_mese.frequencies[0] - 4.0 / 3.0).abs() < 1e-4);
+        assert!((on_mese.frequencies[4] / on_mese.frequencies[3] - 9.0 / 8.0).abs() < 1e-4);
+        
+        // Quarter-comma meantone from E: a pure minor sixth (E-C) and a narrowed fifth (E-B)
+        let meantone = mode_scale(Mode::Dorios, "E", 8, Temperament::Meantone { fraction: MeantoneFraction::Quarter }, &ReferencePitch::default(), 0);
+        assert!((meantone.frequencies[5] / meantone.frequencies[0] - 8.0 / 5.0).abs() < 1e-4);
+        let fifth = cents_between(meantone.frequencies[4], meantone.frequencies[0]);
+        assert!((fifth - (cents_between(3.0, 2.0) - cents_between(81.0, 80.0) / 4.0)).abs() < 0.01);
+        
+        // The ancient just table is read from the tonic too: the fourth above E is pure
+        let ancient = mode_scale(Mode::Dorios, "E", 8, Temperament::JustAncient, &ReferencePitch::default(), 0);
+        assert!((cents_between(ancient.frequencies[3], ancient.frequencies[0]) - 498.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,182 @@
+// Where the analysis thread gets its audio from. Every source delivers interleaved f32 samples
+// in [-1, 1], so the live input, a WAV file and a generated test signal all reach `AudioState`
+// through exactly the same analysis code.
+
+use crate::wav::WavRecording;
+use rtrb::Consumer;
+
+pub trait AudioSource {
+    fn sample_rate(&self) -> u32;
+    
+    fn channels(&self) -> usize;
+    
+    // Fills the start of `buffer` with whole or partial frames and returns how many samples were
+    // written. Zero means nothing is available yet; `None` means the source has ended.
+    fn read(&mut self, buffer: &mut [f32]) -> Option<usize>;
+    
+    // Frames lost on the way in so far, each replaced by silence so later audio keeps its time.
+    // A read that raises the count starts with the silence for the newly lost frames.
+    fn lost_frames(&self) -> usize {
+        0
+    }
+}
+
+// Samples the input callback had to drop because the queue was full; `position` is how many
+// samples had been queued before them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputGap {
+    pub position: u64,
+    pub samples: usize,
+}
+
+// Samples queued by the cpal input callback
+pub struct LiveSource {
+    consumer: Consumer<f32>,
+    gaps: Consumer<InputGap>,
+    sample_rate: u32,
+    channels: usize,
+    samples_read: u64,
+    silence_left: usize,
+    lost_frames: usize,
+}
+
+impl LiveSource {
+    pub fn new(consumer: Consumer<f32>, gaps: Consumer<InputGap>, sample_rate: u32, channels: usize) -> Self {
+        Self {
+            consumer,
+            gaps,
+            sample_rate,
+            channels,
+            samples_read: 0,
+            silence_left: 0,
+            lost_frames: 0,
+        }
+    }
+}
+
+impl AudioSource for LiveSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    
+    fn channels(&self) -> usize {
+        self.channels
+    }
+    
+    // Ends once the stream (and with it the producer) has been dropped
+    fn read(&mut self, buffer: &mut [f32]) -> Option<usize> {
+        if self.consumer.is_abandoned() {
+            return None;
+        }
+        
+        // Silence stands in for dropped samples, so a recording keeps its length and everything
+        // after the gap keeps its time
+        if self.gaps.peek().is_ok_and(|gap| gap.position == self.samples_read) {
+            let gap = self.gaps.pop().ok()?;
+            self.silence_left = gap.samples;
+            self.lost_frames += gap.samples / self.channels.max(1);
+        }
+        if self.silence_left > 0 {
+            let count = self.silence_left.min(buffer.len());
+            buffer[..count].fill(0.0);
+            self.silence_left -= count;
+            return Some(count);
+        }
+        
+        // The callback announces a gap before queueing anything after it, so checking for one
+        // after counting the queued samples never reads past it
+        let mut count = self.consumer.slots().min(buffer.len());
+        if let Ok(gap) = self.gaps.peek() {
+            count = count.min((gap.position - self.samples_read) as usize);
+        }
+        let chunk = self.consumer.read_chunk(count).ok()?;
+        let (first, second) = chunk.as_slices();
+        buffer[..first.len()].copy_from_slice(first);
+        buffer[first.len()..count].copy_from_slice(second);
+        chunk.commit_all();
+        self.samples_read += count as u64;
+        Some(count)
+    }
+    
+    fn lost_frames(&self) -> usize {
+        self.lost_frames
+    }
+}
+
+// A WAV recording, or a range of it, played back as fast as it can be analysed
+pub struct WavSource<'a> {
+    recording: &'a WavRecording,
+    position: usize,
+    end: usize,
+}
+
+impl<'a> WavSource<'a> {
+    // Plays frames `start..end`
+    pub fn new(recording: &'a WavRecording, start: usize, end: usize) -> Self {
+        let end = end.min(recording.frames());
+        Self {
+            recording,
+            position: start.min(end) * recording.channels,
+            end: end * recording.channels,
+        }
+    }
+}
+
+impl AudioSource for WavSource<'_> {
+    fn sample_rate(&self) -> u32 {
+        self.recording.sample_rate
+    }
+    
+    fn channels(&self) -> usize {
+        self.recording.channels
+    }
+    
+    fn read(&mut self, buffer: &mut [f32]) -> Option<usize> {
+        if self.position >= self.end {
+            return None;
+        }
+        
+        let count = (self.end - self.position).min(buffer.len());
+        buffer[..count].copy_from_slice(&self.recording.samples[self.position..self.position + count]);
+        self.position += count;
+        Some(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtrb::RingBuffer;
+    
+    fn read_all(source: &mut LiveSource) -> Vec<f32> {
+        let mut samples = Vec::new();
+        let mut buffer = [0.0; 3];
+        while let Some(count) = source.read(&mut buffer) {
+            if count == 0 {
+                break;
+            }
+            samples.extend_from_slice(&buffer[..count]);
+        }
+        samples
+    }
+    
+    #[test]
+    fn test_live_source_fills_gaps_with_silence() {
+        let (mut producer, consumer) = RingBuffer::new(16);
+        let (mut gap_producer, gaps) = RingBuffer::new(4);
+        let mut source = LiveSource::new(consumer, gaps, 44100, 2);
+        
+        // Two frames, four dropped, then two more
+        for sample in [1.0, 1.0, 2.0, 2.0] {
+            producer.push(sample).unwrap();
+        }
+        gap_producer.push(InputGap { position: 4, samples: 8 }).unwrap();
+        for sample in [3.0, 3.0, 4.0, 4.0] {
+            producer.push(sample).unwrap();
+        }
+        
+        let samples = read_all(&mut source);
+        assert_eq!(samples, [1.0, 1.0, 2.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 3.0, 3.0, 4.0, 4.0]);
+        assert_eq!(source.lost_frames(), 4);
+    }
+}
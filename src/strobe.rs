@@ -0,0 +1,100 @@
+// Strobe tuner measurement. The latest samples are demodulated at the target string's frequency
+// (and a couple of its partials) against an absolute sample clock, so the phase of each band
+// stands still when the string is exactly in tune and turns at the mistuning in Hz otherwise:
+// forwards when sharp, backwards when flat. A strobe display drawn from these phases shows
+// fractions of a cent that a spectrum plot cannot.
+
+// Partials shown as strobe bands, from the top row down; higher partials turn faster
+pub const STROBE_PARTIALS: [usize; 3] = [1, 2, 4];
+// Each measurement spans at least this many periods of the target, so low strings aren't
+// smeared by their own harmonics leaking into a window only a cycle or two long
+const STROBE_WINDOW_PERIODS: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrobePhase {
+    pub target: f32,
+    // Phase of each band in turns (0..1)
+    pub phases: [f32; STROBE_PARTIALS.len()],
+    // Amplitude of each band relative to the loudest one (0..1)
+    pub levels: [f32; STROBE_PARTIALS.len()],
+}
+
+// Samples a measurement at `target` should span
+pub fn strobe_window(target: f32, sample_rate: u32) -> usize {
+    (STROBE_WINDOW_PERIODS * sample_rate as f32 / target) as usize
+}
+
+// `samples` are the most recent samples, the last of which is number `end_clock` since the
+// stream started
+pub fn measure_strobe(samples: &[f32], end_clock: u64, sample_rate: u32, target: f32) -> StrobePhase {
+    let start_clock = end_clock.saturating_sub(samples.len() as u64);
+    let mut phases = [0.0; STROBE_PARTIALS.len()];
+    let mut amplitudes = [0.0; STROBE_PARTIALS.len()];
+    
+    for (band, &partial) in STROBE_PARTIALS.iter().enumerate() {
+        // Cycles per sample; the clock is reduced modulo one cycle in f64 so the reference stays
+        // exact however long the stream has been running
+        let cycles_per_sample = target as f64 * partial as f64 / sample_rate as f64;
+        let (mut re, mut im) = (0.0f64, 0.0f64);
+        for (i, &sample) in samples.iter().enumerate() {
+            // Hann weighting keeps neighbouring strings and partials out of the band
+            let weight = 0.5 - 0.5 * (std::f64::consts::TAU * i as f64 / samples.len() as f64).cos();
+            let sample = sample as f64 * weight;
+            let clock = start_clock + i as u64;
+            let turns = (clock as f64 * cycles_per_sample).fract();
+            let (sin, cos) = (std::f64::consts::TAU * turns).sin_cos();
+            re += sample * cos;
+            im -= sample * sin;
+        }
+        
+        // Phase of the signal against the reference; it advances when the signal runs faster
+        phases[band] = (im.atan2(re) / std::f64::consts::TAU).rem_euclid(1.0) as f32;
+        amplitudes[band] = (re * re + im * im).sqrt() as f32;
+    }
+    
+    let loudest = amplitudes.iter().cloned().fold(0.0, f32::max);
+    let levels = amplitudes.map(|amplitude| if loudest > 0.0 { amplitude / loudest } else { 0.0 });
+    
+    StrobePhase {
+        target,
+        phases,
+        levels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SAMPLE_RATE: u32 = 44100;
+    
+    // `count` samples of a sine at `frequency`, the last of which is sample `end_clock`
+    fn sine(frequency: f32, end_clock: u64, count: usize) -> Vec<f32> {
+        let start = end_clock - count as u64;
+        (start..end_clock)
+            .map(|clock| (std::f64::consts::TAU * frequency as f64 * clock as f64 / SAMPLE_RATE as f64).sin() as f32)
+            .collect()
+    }
+    
+    // How far the first band turns, in turns, between two measurements `seconds` apart
+    fn band_turn(frequency: f32, target: f32, seconds: f32) -> f32 {
+        let count = strobe_window(target, SAMPLE_RATE);
+        let first_end = 10 * SAMPLE_RATE as u64;
+        let second_end = first_end + (seconds * SAMPLE_RATE as f32) as u64;
+        let first = measure_strobe(&sine(frequency, first_end, count), first_end, SAMPLE_RATE, target);
+        let second = measure_strobe(&sine(frequency, second_end, count), second_end, SAMPLE_RATE, target);
+        (second.phases[0] - first.phases[0] + 0.5).rem_euclid(1.0) - 0.5
+    }
+    
+    #[test]
+    fn test_strobe_stands_still_in_tune() {
+        assert!(band_turn(220.0, 220.0, 0.1).abs() < 1e-3);
+    }
+    
+    #[test]
+    fn test_strobe_turns_at_the_mistuning() {
+        // 0.5 Hz sharp turns forwards half a turn per second, 0.5 Hz flat backwards
+        assert!((band_turn(220.5, 220.0, 0.2) - 0.1).abs() < 5e-3);
+        assert!((band_turn(219.5, 220.0, 0.2) + 0.1).abs() < 5e-3);
+    }
+}
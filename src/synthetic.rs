@@ -0,0 +1,184 @@
+// Generated test signals for driving the analysis in tests without an audio device
+
+use crate::source::AudioSource;
+
+// Test signal: a string-like tone built from harmonics, with optional detuning, stretched
+// partials, exponential decay and white noise. Output is fully deterministic (the noise comes
+// from a fixed-seed generator) so tests get the same samples on every run.
+#[derive(Clone, Debug)]
+pub struct SyntheticSignal {
+    pub frequency: f32,
+    // Amplitude of each harmonic, fundamental first
+    pub harmonics: Vec<f32>,
+    pub detune_cents: f32,
+    // Partial n sits at n * f * sqrt(1 + B n^2), as on a stiff string
+    pub inharmonicity: f32,
+    // Amplitude falls by 1/e every `1 / decay` seconds; zero sustains forever
+    pub decay: f32,
+    pub noise: f32,
+    // Silence until the tone starts; `duration_secs` counts from the very beginning
+    pub start_secs: f32,
+    pub duration_secs: f32,
+    pub seed: u64,
+}
+
+impl Default for SyntheticSignal {
+    fn default() -> Self {
+        Self {
+            frequency: 220.0,
+            harmonics: vec![1.0, 0.5, 0.25],
+            detune_cents: 0.0,
+            inharmonicity: 0.0,
+            decay: 0.0,
+            noise: 0.0,
+            start_secs: 0.0,
+            duration_secs: 2.0,
+            seed: 1,
+        }
+    }
+}
+
+// Interval from `reference` up to `frequency`, for comparing readings against expectations
+pub fn cents_between(frequency: f32, reference: f32) -> f32 {
+    1200.0 * (frequency / reference).log2()
+}
+
+// Where along the string the synthetic pluck happens, as a fraction of its length
+const PLUCK_POSITION: f32 = 0.18;
+
+impl SyntheticSignal {
+    // An idealised plucked string: harmonic n has amplitude sin(n * pi * p) / n^2 for a pluck at
+    // fraction p of the length, and the whole tone dies away over a few seconds
+    pub fn plucked(frequency: f32) -> Self {
+        let harmonics = (1..=12)
+            .map(|n| (n as f32 * std::f32::consts::PI * PLUCK_POSITION).sin().abs() / (n * n) as f32)
+            .collect();
+        
+        Self {
+            frequency,
+            harmonics,
+            decay: 1.0,
+            duration_secs: 1.5,
+            ..Default::default()
+        }
+    }
+}
+
+// A partial of one of the generated tones
+struct Partial {
+    frequency: f64,
+    amplitude: f32,
+    decay: f32,
+    start: f64,
+}
+
+pub struct SyntheticSource {
+    sample_rate: u32,
+    channels: usize,
+    partials: Vec<Partial>,
+    noise: f32,
+    rng_state: u64,
+    frame: usize,
+    channel: usize,
+    frames: usize,
+    current: f32,
+}
+
+impl SyntheticSource {
+    pub fn new(signal: &SyntheticSignal, sample_rate: u32, channels: usize) -> Self {
+        Self::mix(std::slice::from_ref(signal), sample_rate, channels)
+    }
+    
+    // Several tones sounding together, as when strings ring into each other. Noise levels add
+    // up, the first signal's seed is used and the output lasts as long as the longest signal.
+    pub fn mix(signals: &[SyntheticSignal], sample_rate: u32, channels: usize) -> Self {
+        let nyquist = sample_rate as f64 / 2.0;
+        let mut partials = Vec::new();
+        
+        for signal in signals {
+            let frequency = signal.frequency as f64 * 2f64.powf(signal.detune_cents as f64 / 1200.0);
+            for (i, &amplitude) in signal.harmonics.iter().enumerate() {
+                let n = (i + 1) as f64;
+                let stretch = (1.0 + signal.inharmonicity as f64 * n * n).sqrt();
+                let partial_frequency = frequency * n * stretch;
+                // Partials past Nyquist would alias back into the band as spurious peaks
+                if partial_frequency < nyquist {
+                    partials.push(Partial {
+                        frequency: partial_frequency,
+                        amplitude,
+                        decay: signal.decay,
+                        start: signal.start_secs as f64,
+                    });
+                }
+            }
+        }
+        
+        let duration_secs = signals.iter().map(|signal| signal.duration_secs).fold(0.0, f32::max);
+        
+        Self {
+            sample_rate,
+            channels: channels.max(1),
+            partials,
+            noise: signals.iter().map(|signal| signal.noise).sum(),
+            rng_state: signals.first().map(|signal| signal.seed).unwrap_or(1).max(1),
+            frame: 0,
+            channel: 0,
+            frames: (duration_secs * sample_rate as f32) as usize,
+            current: 0.0,
+        }
+    }
+    
+    // xorshift64, mapped to [-1, 1)
+    fn next_noise(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        (self.rng_state >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+    
+    fn next_frame_value(&mut self) -> f32 {
+        let time = self.frame as f64 / self.sample_rate as f64;
+        let tone: f32 = self.partials.iter()
+            .filter(|partial| time >= partial.start)
+            .map(|partial| {
+                let elapsed = time - partial.start;
+                let envelope = (-partial.decay * elapsed as f32).exp();
+                let phase = 2.0 * std::f64::consts::PI * partial.frequency * elapsed;
+                partial.amplitude * envelope * phase.sin() as f32
+            })
+            .sum();
+        tone + self.noise * self.next_noise()
+    }
+}
+
+impl AudioSource for SyntheticSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    
+    fn channels(&self) -> usize {
+        self.channels
+    }
+    
+    // Every channel carries the same signal
+    fn read(&mut self, buffer: &mut [f32]) -> Option<usize> {
+        if self.frame >= self.frames {
+            return None;
+        }
+        
+        let mut count = 0;
+        while count < buffer.len() && self.frame < self.frames {
+            if self.channel == 0 {
+                self.current = self.next_frame_value();
+            }
+            buffer[count] = self.current;
+            count += 1;
+            self.channel += 1;
+            if self.channel == self.channels {
+                self.channel = 0;
+                self.frame += 1;
+            }
+        }
+        Some(count)
+    }
+}
@@ -1,17 +1,53 @@
-use crate::audio::{AudioState, play_notes_descending};
-use crate::scales::{ScaleType, Mode, Genus, Temperament, ScaleData, get_string_count_defaults};
+use crate::audio::{AnalysisSettings, AudioState, AudioStatus, ChannelSelection, InputDevice, RecordRequest, RecordingAnalyzer, WindowFunction, FFT_SIZES, list_input_devices, play_notes_descending};
+use crate::filters::HumFrequency;
+use crate::pitch::{PitchEngine, MIN_CONFIDENCE};
+use crate::scales::{ScaleType, Mode, Genus, Temperament, MeantoneFraction, WellTemperament, ScaleData, ReferencePitch, CONCERT_PITCH_PRESETS, get_string_count_defaults};
+use crate::strobe::{StrobePhase, STROBE_PARTIALS};
+use crate::wav::WavRecording;
 use eframe::egui;
 use egui_plot::{Plot, Line};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+// A fitted inharmonicity is credited to the string within this many cents of the note, and
+// folded into that string's running value with this weight
+const INHARMONICITY_MATCH_CENTS: f32 = 50.0;
+const INHARMONICITY_SMOOTHING: f32 = 0.2;
+
+// Largest in-tune tolerance the sliders offer
+const MAX_TOLERANCE_CENTS: f32 = 25.0;
+
+// Strobe display: height of each band and how many light/dark stripe pairs span its width
+const STROBE_BAND_HEIGHT: f32 = 18.0;
+const STROBE_STRIPES: usize = 16;
+
+// Needle gauge: the deviation it spans either side of the target, its height, and the weight
+// each new reading gets so the needle glides instead of jittering
+const NEEDLE_RANGE_CENTS: f32 = 50.0;
+const NEEDLE_GAUGE_HEIGHT: f32 = 110.0;
+const NEEDLE_SMOOTHING: f32 = 0.3;
+
+// How the deviation from the target is shown
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TunerDisplay {
+    Cents,
+    Strobe,
+    Needle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum NoteFilter {
     TonesOnly,
     TonesAndSemitones,
     All,
 }
 
+// Everything the user can set is saved between runs; derived and transient state is skipped
+// and comes from `Default` when loading
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct UiState {
     pub num_strings: usize,
     pub first_note: String,
@@ -20,13 +56,40 @@ pub struct UiState {
     pub genus: Genus,
     pub temperament: Temperament,
     pub octave_offset: i32,
+    pub reference_pitch: ReferencePitch,
+    // The string just, meantone and Pythagorean tunings are built on
+    pub tonic_string: usize,
     pub zoom: f32,
-    pub tolerance: f32,
+    // How close a string must be to its target to count as in tune. Measured in cents so it means
+    // the same on every string; a string can have its own value instead of the common one.
+    pub tolerance_cents: f32,
+    pub string_tolerances: Vec<Option<f32>>,
+    pub tune_to_perceived: bool,
+    pub tuner_display: TunerDisplay,
     pub show_full_spectrum: bool,
+    #[serde(skip)]
     pub scale_data: ScaleData,
+    #[serde(skip)]
     pub note_hits: Vec<Instant>,
+    // Measured inharmonicity coefficient B of each string, once it has been heard
+    #[serde(skip)]
+    pub inharmonicity: Vec<Option<f32>>,
+    // The string last heard, which the strobe and needle keep following between plucks, and the
+    // needle's smoothed deviation from its target
+    #[serde(skip)]
+    pub current_string: Option<usize>,
+    #[serde(skip)]
+    pub needle_cents: Option<f32>,
     pub note_filter: NoteFilter,
     pub magnitude_scale: f32,
+    pub analysis_settings: AnalysisSettings,
+    pub input_device: Option<InputDevice>,
+    #[serde(skip)]
+    pub input_devices: Vec<InputDevice>,
+    #[serde(skip)]
+    pub recording: Option<RecordingAnalyzer>,
+    #[serde(skip)]
+    pub recording_error: Option<String>,
 }
 
 impl Default for UiState {
@@ -42,8 +105,13 @@ impl Default for UiState {
             genus: Genus::Diatonic,
             temperament: Temperament::JustAncient,
             octave_offset: 0,
+            reference_pitch: ReferencePitch::default(),
+            tonic_string: 0,
             zoom,
-            tolerance: 1.5,
+            tolerance_cents: 5.0,
+            string_tolerances: vec![None; num_strings],
+            tune_to_perceived: false,
+            tuner_display: TunerDisplay::Cents,
             show_full_spectrum: false,
             scale_data: ScaleData::new(
                 ScaleType::Modes,
@@ -53,14 +121,54 @@ impl Default for UiState {
                 num_strings,
                 Temperament::JustAncient,
                 0,
+                &ReferencePitch::default(),
+                0,
             ),
             note_hits: vec![Instant::now(); 24],
+            inharmonicity: vec![None; num_strings],
+            current_string: None,
+            needle_cents: None,
             note_filter: NoteFilter::TonesOnly,
             magnitude_scale: 30.0,
+            analysis_settings: AnalysisSettings::default(),
+            input_device: None,
+            input_devices: Vec::new(),
+            recording: None,
+            recording_error: None,
         }
     }
 }
 
+impl UiState {
+    pub fn restore(storage: Option<&dyn eframe::Storage>) -> Self {
+        let mut ui_state: UiState = storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        update_scale_data(&mut ui_state);
+        ui_state
+    }
+    
+    // In-tune tolerance of a string, in cents
+    pub fn tolerance_for(&self, index: usize) -> f32 {
+        self.string_tolerances.get(index).copied().flatten().unwrap_or(self.tolerance_cents)
+    }
+    
+    // Switches the display from live input to the given WAV file
+    pub fn open_recording(&mut self, path: &Path) {
+        match WavRecording::open(path) {
+            Ok(recording) => {
+                self.recording = Some(RecordingAnalyzer::new(recording));
+                self.recording_error = None;
+            }
+            Err(message) => {
+                eprintln!("{}", message);
+                self.recording_error = Some(message);
+            }
+        }
+    }
+}
+
+#[allow(clippy::collapsible_if)]
 pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &egui::Context) {
     egui::CentralPanel::default()
         .frame(egui::Frame::default().fill(egui::Color32::BLACK))
@@ -74,6 +182,11 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
         
         ui.separator();
         
+        show_input_device_row(ui_state, audio_state, ui);
+        show_recording_row(ui_state, ui);
+        
+        ui.separator();
+        
         ui.horizontal(|ui| {
             ui.label("Number of strings:");
             if ui.add(egui::Slider::new(&mut ui_state.num_strings, 4..=24)).changed() {
@@ -92,7 +205,7 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
             ui.separator();
             
             ui.label("First note:");
-                     
+            
             egui::ComboBox::from_id_salt("first_note_combo")
                 .selected_text(&ui_state.first_note)
                 .show_ui(ui, |ui| {
@@ -198,11 +311,25 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
             ui.separator();
             
             ui.label("Temperament:");
+            // Switching between well temperaments keeps the chosen key
+            let well_key = match ui_state.temperament {
+                Temperament::Well { key, .. } => key,
+                _ => 0,
+            };
+            let well = |temperament| Temperament::Well { temperament, key: well_key };
             let temperament_labels = [
                 (Temperament::Equal, "Equal"),
                 (Temperament::Just, "Just (Modern)"),
                 (Temperament::JustAncient, "Just (Ancient Greek/Indian)"),
-                (Temperament::Meantone, "Meantone"),
+                (Temperament::Meantone { fraction: MeantoneFraction::Third }, "Meantone (1/3 comma)"),
+                (Temperament::Meantone { fraction: MeantoneFraction::Quarter }, "Meantone (1/4 comma)"),
+                (Temperament::Meantone { fraction: MeantoneFraction::Fifth }, "Meantone (1/5 comma)"),
+                (Temperament::Meantone { fraction: MeantoneFraction::Sixth }, "Meantone (1/6 comma)"),
+                (Temperament::Pythagorean, "Pythagorean (pure 3:2 fifths)"),
+                (well(WellTemperament::Werckmeister), "Well: Werckmeister III"),
+                (well(WellTemperament::Kirnberger), "Well: Kirnberger III"),
+                (well(WellTemperament::Vallotti), "Well: Vallotti"),
+                (well(WellTemperament::Young), "Well: Young"),
             ];
             
             let current_label = temperament_labels.iter()
@@ -220,6 +347,74 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
                     }
                 });
             
+            // The key a well temperament favours, independent of the first note
+            if let Temperament::Well { key, .. } = &mut ui_state.temperament {
+                let key_names = ["C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+                ui.label("Key:");
+                let mut changed = false;
+                egui::ComboBox::from_id_salt("well_key_combo")
+                    .selected_text(key_names[*key as usize % 12])
+                    .show_ui(ui, |ui| {
+                        for (index, name) in key_names.iter().enumerate() {
+                            changed |= ui.selectable_value(key, index as i32, *name).changed();
+                        }
+                    });
+                if changed {
+                    update_scale_data(ui_state);
+                }
+            }
+            
+            let tonic_relative = matches!(
+                ui_state.temperament,
+                Temperament::Just | Temperament::JustAncient | Temperament::Meantone { .. } | Temperament::Pythagorean
+            );
+            if tonic_relative {
+                ui.label("Tonic:");
+                let tonic_label = |index: usize, note: &str| match index {
+                    0 => format!("{} (first string)", note),
+                    _ => note.to_string(),
+                };
+                let selected = ui_state.tonic_string.min(ui_state.scale_data.notes.len().saturating_sub(1));
+                let selected_text = ui_state.scale_data.notes.get(selected)
+                    .map(|note| tonic_label(selected, note))
+                    .unwrap_or_default();
+                let mut changed = false;
+                egui::ComboBox::from_id_salt("tonic_combo")
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        for (index, note) in ui_state.scale_data.notes.iter().enumerate() {
+                            changed |= ui.selectable_value(&mut ui_state.tonic_string, index, tonic_label(index, note)).changed();
+                        }
+                    });
+                if changed {
+                    update_scale_data(ui_state);
+                }
+            }
+            
+            ui.separator();
+            
+            ui.label("Concert pitch:");
+            let reference = &ui_state.reference_pitch;
+            egui::ComboBox::from_id_salt("concert_pitch_combo")
+                .selected_text(format!("{}{} = {} Hz", reference.note, reference.octave, (reference.frequency * 10.0).round() / 10.0))
+                .show_ui(ui, |ui| {
+                    for (frequency, label) in CONCERT_PITCH_PRESETS {
+                        let is_selected = ui_state.reference_pitch == ReferencePitch::a4(frequency);
+                        if ui.selectable_label(is_selected, label).clicked() {
+                            ui_state.reference_pitch = ReferencePitch::a4(frequency);
+                            update_scale_data(ui_state);
+                        }
+                    }
+                });
+            let frequency_range = ui_state.reference_pitch.frequency_range();
+            let frequency = egui::DragValue::new(&mut ui_state.reference_pitch.frequency)
+                .range(frequency_range)
+                .speed(0.1)
+                .suffix(" Hz");
+            if ui.add(frequency).changed() {
+                update_scale_data(ui_state);
+            }
+            
             ui.separator();
             
             if ui.small_button("🔊").clicked() {
@@ -229,30 +424,133 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
         
         ui.separator();
         
-        let audio_data = audio_state.lock().unwrap();
+        // With a recording open, its analysis replaces the live input in everything below
+        // The strobe is only measured while it is on screen
+        let strobe_target = ui_state.current_string
+            .filter(|_| ui_state.tuner_display == TunerDisplay::Strobe)
+            .map(|index| ui_state.scale_data.frequencies[index]);
+        let displayed_state = match &mut ui_state.recording {
+            Some(recording) => {
+                recording.update(&ui_state.analysis_settings, &ui_state.scale_data.frequencies, strobe_target);
+                recording.audio_state.clone()
+            }
+            None => audio_state.clone(),
+        };
+        
+        let mut audio_data = displayed_state.lock().unwrap();
+        // Keeps the selector showing the channel actually analysed when the input changes
+        if audio_data.channels > 0 {
+            ui_state.analysis_settings.channel = ui_state.analysis_settings.channel.clamped(audio_data.channels as usize);
+        }
+        audio_data.settings = ui_state.analysis_settings;
+        audio_data.targets.clone_from(&ui_state.scale_data.frequencies);
+        audio_data.strobe_target = strobe_target;
+        // Readings made against an older scale no longer line up with the strings
+        let string_readings = if audio_data.string_readings.len() == ui_state.scale_data.frequencies.len() {
+            audio_data.string_readings.clone()
+        } else {
+            Vec::new()
+        };
         let peak_freq = audio_data.peak_frequency;
+        let confidence = audio_data.confidence;
+        let pluck = audio_data.pluck;
+        let partial_fit = audio_data.partial_fit;
+        let strobe = audio_data.strobe.filter(|strobe| Some(strobe.target) == strobe_target);
+        let input_channels = audio_data.channels as usize;
         let freq_data = audio_data.frequency_data.clone();
         let sample_rate = audio_data.sample_rate;
         drop(audio_data);
         
+        if let Some(fit) = partial_fit {
+            if let Some((index, target_freq)) = find_closest_note(&ui_state.scale_data, fit.fundamental) {
+                let is_near = (1200.0 * (fit.fundamental / target_freq).log2()).abs() < INHARMONICITY_MATCH_CENTS;
+                if let Some(measured) = ui_state.inharmonicity.get_mut(index).filter(|_| is_near) {
+                    *measured = Some(measured.map_or(fit.inharmonicity, |previous| {
+                        previous + INHARMONICITY_SMOOTHING * (fit.inharmonicity - previous)
+                    }));
+                }
+            }
+        }
+        
+        // Whether this frame had a reading for the needle; otherwise it holds its last position
+        let mut heard = false;
         ui.horizontal(|ui| {
-            ui.label(format!("Peak frequency: {:.1} Hz", peak_freq));
+            ui.label(format!("Peak frequency: {:.2} Hz", peak_freq));
+            ui.label(format!("Confidence: {:.0}%", confidence * 100.0));
             
-            let closest_note = find_closest_note(&ui_state.scale_data, peak_freq);
+            // With onset detection, the reading is the current pluck's stabilized pitch rather
+            // than the latest frame; until its first frame arrives the live reading stands in
+            let (frequency, confidence) = match pluck {
+                Some(pluck) if pluck.frames > 0 => {
+                    ui.separator();
+                    let text = format!("Pluck {}: {:.2} Hz", pluck.id, pluck.frequency);
+                    if pluck.ringing {
+                        ui.label(text);
+                        (pluck.frequency, pluck.confidence)
+                    } else {
+                        ui.colored_label(egui::Color32::GRAY, text);
+                        return;
+                    }
+                }
+                _ => (peak_freq, confidence),
+            };
+            
+            // The stretched upper partials make a stiff string sound a little sharp of its
+            // fundamental; optionally tune that perceived pitch to the target instead
+            let frequency = match partial_fit {
+                Some(fit) if ui_state.tune_to_perceived => frequency * fit.perceived / fit.fundamental,
+                _ => frequency,
+            };
+            
+            // Ignore ambiguous frames (noise, several strings, transients)
+            if confidence < MIN_CONFIDENCE {
+                return;
+            }
+            
+            let closest_note = find_closest_note(&ui_state.scale_data, frequency);
             if let Some((index, target_freq)) = closest_note {
-                let diff = peak_freq - target_freq;
-                if diff.abs() < ui_state.tolerance {
+                let cents = 1200.0 * (frequency / target_freq).log2();
+                if cents.abs() < ui_state.tolerance_for(index) {
                     ui_state.note_hits[index] = Instant::now();
                 }
                 
+                // A different string starts the needle afresh rather than sweeping it across
+                if ui_state.current_string != Some(index) {
+                    ui_state.needle_cents = None;
+                }
+                ui_state.current_string = Some(index);
+                
                 ui.separator();
                 ui.label(format!("Target: {} ({:.1} Hz)", ui_state.scale_data.notes[index], target_freq));
                 
-                let cents = 1200.0 * (peak_freq / target_freq).log2();
-                ui.label(format!("Difference: {:.1} cents", cents));
+                ui_state.needle_cents = Some(ui_state.needle_cents.map_or(cents, |previous| {
+                    previous + NEEDLE_SMOOTHING * (cents - previous)
+                }));
+                heard = true;
+                if ui_state.tuner_display == TunerDisplay::Cents {
+                    ui.label(format!("Difference: {:.1} cents", cents));
+                }
             }
         });
         
+        match ui_state.tuner_display {
+            TunerDisplay::Cents => {}
+            TunerDisplay::Strobe => show_strobe(ui, strobe),
+            TunerDisplay::Needle => {
+                let tolerance_cents = ui_state.current_string.map_or(ui_state.tolerance_cents, |index| ui_state.tolerance_for(index));
+                show_needle(ui, ui_state.needle_cents, tolerance_cents, heard);
+            }
+        }
+        
+        // Every string heard in this frame, so a strum can turn several strings green at once
+        for (i, reading) in string_readings.iter().enumerate() {
+            if let Some(reading) = reading {
+                if reading.cents.abs() < ui_state.tolerance_for(i) {
+                    ui_state.note_hits[i] = Instant::now();
+                }
+            }
+        }
+        
         ui.separator();
         
         ui.label("Notes:");
@@ -268,7 +566,15 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
                     egui::Color32::YELLOW
                 };
                 
-                ui.colored_label(color, format!("{}: {:.1}Hz", note, freq));
+                // Strings sounding right now are marked and show how far off they are
+                let mut label = match string_readings.get(i).copied().flatten() {
+                    Some(reading) => format!("● {}: {:.1}Hz {:+.1}¢", note, freq, reading.cents),
+                    None => format!("{}: {:.1}Hz", note, freq),
+                };
+                if let Some(inharmonicity) = ui_state.inharmonicity.get(i).copied().flatten() {
+                    label += &format!(" B={:.1e}", inharmonicity);
+                }
+                ui.colored_label(color, label);
                 ui.separator();
             }
         });
@@ -316,7 +622,7 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
             .allow_zoom([true, false]) // Allow horizontal zoom only
             .show_grid(false) // Disable grid
             .clamp_grid(true); // Clamp to prevent going outside bounds
-            
+        
         // Set the clamping bounds
         if !ui_state.show_full_spectrum {
             plot = plot.set_margin_fraction(egui::Vec2::new(0.0, 0.1));
@@ -411,14 +717,62 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
                 }
             });
             
+            ui.horizontal(|ui| {
+                // Moving the reference to another note keeps the strings where they are: the new
+                // note takes the frequency the current tuning already gives it
+                ui.label("Concert pitch reference note:");
+                let mut note = ui_state.reference_pitch.note.clone();
+                egui::ComboBox::from_id_salt("reference_note_combo")
+                    .selected_text(&note)
+                    .show_ui(ui, |ui| {
+                        for name in ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"] {
+                            ui.selectable_value(&mut note, name.to_string(), name);
+                        }
+                    });
+                ui.label("Octave:");
+                let mut octave = ui_state.reference_pitch.octave;
+                ui.add(egui::DragValue::new(&mut octave).range(0..=8));
+                if note != ui_state.reference_pitch.note || octave != ui_state.reference_pitch.octave {
+                    ui_state.reference_pitch = ReferencePitch {
+                        frequency: ui_state.scale_data.frequency_of(&note, octave),
+                        note,
+                        octave,
+                    };
+                    update_scale_data(ui_state);
+                }
+            });
+            
             ui.horizontal(|ui| {
                 ui.label("Zoom:");
                 ui.add(egui::Slider::new(&mut ui_state.zoom, 0.25..=4.0));
             });
             
             ui.horizontal(|ui| {
-                ui.label("Tolerance (Hz):");
-                ui.add(egui::Slider::new(&mut ui_state.tolerance, 0.0..=5.0));
+                ui.label("Tolerance (cents):");
+                ui.add(egui::Slider::new(&mut ui_state.tolerance_cents, 0.0..=MAX_TOLERANCE_CENTS));
+            });
+            
+            ui.collapsing("Per-string tolerance", |ui| {
+                for (note, tolerance) in ui_state.scale_data.notes.iter().zip(ui_state.string_tolerances.iter_mut()) {
+                    ui.horizontal(|ui| {
+                        let mut overridden = tolerance.is_some();
+                        if ui.checkbox(&mut overridden, note.as_str()).changed() {
+                            *tolerance = overridden.then_some(ui_state.tolerance_cents);
+                        }
+                        if let Some(cents) = tolerance {
+                            ui.add(egui::Slider::new(cents, 0.0..=MAX_TOLERANCE_CENTS).suffix("¢"));
+                        }
+                    });
+                }
+            });
+            
+            ui.checkbox(&mut ui_state.tune_to_perceived, "Tune to perceived pitch (weights the sharp upper partials)");
+            
+            ui.horizontal(|ui| {
+                ui.label("Tuner display:");
+                ui.radio_value(&mut ui_state.tuner_display, TunerDisplay::Cents, "Cents");
+                ui.radio_value(&mut ui_state.tuner_display, TunerDisplay::Strobe, "Strobe");
+                ui.radio_value(&mut ui_state.tuner_display, TunerDisplay::Needle, "Needle");
             });
             
             ui.horizontal(|ui| {
@@ -457,11 +811,256 @@ pub fn show(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ctx: &
                     });
             });
             
+            ui.horizontal(|ui| {
+                ui.label("Input channel:");
+                let channel_label = |channel: ChannelSelection| match channel {
+                    ChannelSelection::Single(index) => format!("Channel {}", index + 1),
+                    ChannelSelection::Average => "Average of all channels".to_string(),
+                    ChannelSelection::Sum => "Sum of all channels".to_string(),
+                };
+                egui::ComboBox::from_id_salt("input_channel_combo")
+                    .selected_text(channel_label(ui_state.analysis_settings.channel))
+                    .show_ui(ui, |ui| {
+                        let mut options: Vec<ChannelSelection> = (0..input_channels.max(1))
+                            .map(ChannelSelection::Single)
+                            .collect();
+                        options.push(ChannelSelection::Average);
+                        options.push(ChannelSelection::Sum);
+                        for channel in options {
+                            ui.selectable_value(&mut ui_state.analysis_settings.channel, channel, channel_label(channel));
+                        }
+                    });
+            });
+            
+            ui.horizontal(|ui| {
+                ui.label("Pitch engine:");
+                egui::ComboBox::from_id_salt("pitch_engine_combo")
+                    .selected_text(match ui_state.analysis_settings.engine {
+                        PitchEngine::Spectral => "Spectral (harmonic sum)",
+                        PitchEngine::Yin => "YIN (time domain)",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut ui_state.analysis_settings.engine, PitchEngine::Spectral, "Spectral (harmonic sum)");
+                        ui.selectable_value(&mut ui_state.analysis_settings.engine, PitchEngine::Yin, "YIN (time domain)");
+                    });
+            });
+            
+            ui.checkbox(&mut ui_state.analysis_settings.polyphonic, "Detect all ringing strings at once");
+            ui.checkbox(&mut ui_state.analysis_settings.onset_detection, "Stabilize the pitch of each pluck (skips the attack)");
+            
+            ui.horizontal(|ui| {
+                ui.label("Analysis hop (samples):");
+                egui::ComboBox::from_id_salt("hop_size_combo")
+                    .selected_text(format!("{}", ui_state.analysis_settings.hop_size))
+                    .show_ui(ui, |ui| {
+                        for hop_size in [512, 1024, 2048, 4096, 8192, 16384, 32768] {
+                            ui.selectable_value(&mut ui_state.analysis_settings.hop_size, hop_size, format!("{}", hop_size));
+                        }
+                    });
+            });
+            
+            ui.horizontal(|ui| {
+                ui.label("FFT size:");
+                egui::ComboBox::from_id_salt("fft_size_combo")
+                    .selected_text(format!("{}", ui_state.analysis_settings.fft_size))
+                    .show_ui(ui, |ui| {
+                        for fft_size in FFT_SIZES {
+                            ui.selectable_value(&mut ui_state.analysis_settings.fft_size, fft_size, format!("{}", fft_size));
+                        }
+                    });
+                
+                ui.separator();
+                
+                ui.label("Window:");
+                let window_labels = [
+                    (WindowFunction::Hann, "Hann"),
+                    (WindowFunction::Hamming, "Hamming"),
+                    (WindowFunction::BlackmanHarris, "Blackman-Harris"),
+                    (WindowFunction::FlatTop, "Flat-top"),
+                ];
+                let current_window_label = window_labels.iter()
+                    .find(|(window, _)| *window == ui_state.analysis_settings.window)
+                    .map(|(_, label)| *label)
+                    .unwrap_or("Unknown");
+                egui::ComboBox::from_id_salt("window_combo")
+                    .selected_text(current_window_label)
+                    .show_ui(ui, |ui| {
+                        for (window, label) in window_labels {
+                            ui.selectable_value(&mut ui_state.analysis_settings.window, window, label);
+                        }
+                    });
+            });
+            
+            let preprocessing = &mut ui_state.analysis_settings.preprocessing;
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut preprocessing.high_pass_enabled, "High-pass filter");
+                ui.add_enabled(
+                    preprocessing.high_pass_enabled,
+                    egui::Slider::new(&mut preprocessing.high_pass_hz, 10.0..=200.0).suffix(" Hz"),
+                );
+            });
+            
+            ui.horizontal(|ui| {
+                ui.label("Hum notch:");
+                egui::ComboBox::from_id_salt("hum_notch_combo")
+                    .selected_text(match preprocessing.hum_frequency {
+                        HumFrequency::Off => "Off",
+                        HumFrequency::Hz50 => "50 Hz",
+                        HumFrequency::Hz60 => "60 Hz",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut preprocessing.hum_frequency, HumFrequency::Off, "Off");
+                        ui.selectable_value(&mut preprocessing.hum_frequency, HumFrequency::Hz50, "50 Hz");
+                        ui.selectable_value(&mut preprocessing.hum_frequency, HumFrequency::Hz60, "60 Hz");
+                    });
+                ui.label("Harmonics:");
+                ui.add_enabled(
+                    preprocessing.hum_frequency != HumFrequency::Off,
+                    egui::Slider::new(&mut preprocessing.hum_harmonics, 1..=8),
+                );
+            });
+            
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut preprocessing.noise_gate_enabled, "Noise gate");
+                ui.add_enabled(
+                    preprocessing.noise_gate_enabled,
+                    egui::Slider::new(&mut preprocessing.noise_gate_db, -90.0..=-20.0).suffix(" dB"),
+                );
+            });
+            
             ui.checkbox(&mut ui_state.show_full_spectrum, "Show full spectrum");
         }).body_returned.is_some() {}
     });
 }
 
+fn show_input_device_row(ui_state: &mut UiState, audio_state: &Arc<Mutex<AudioState>>, ui: &mut egui::Ui) {
+    let (status, is_recording, record_elapsed, record_lost, record_error) = {
+        let state = audio_state.lock().unwrap();
+        (state.status.clone(), state.record_request.is_some(), state.record_elapsed, state.record_lost, state.record_error.clone())
+    };
+    
+    ui.horizontal(|ui| {
+        ui.label("Input device:");
+        
+        let device_label = |device: &InputDevice| format!("{} ({})", device.device_name, device.host_name);
+        let selected_text = ui_state.input_device.as_ref()
+            .map(device_label)
+            .unwrap_or_else(|| "System default".to_string());
+        
+        let mut selection_changed = false;
+        egui::ComboBox::from_id_salt("input_device_combo")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                // Enumerating devices can be slow, so only do it once the list is opened
+                if ui_state.input_devices.is_empty() {
+                    ui_state.input_devices = list_input_devices();
+                }
+                
+                if ui.selectable_label(ui_state.input_device.is_none(), "System default").clicked() {
+                    ui_state.input_device = None;
+                    selection_changed = true;
+                }
+                for device in &ui_state.input_devices {
+                    let is_selected = ui_state.input_device.as_ref() == Some(device);
+                    if ui.selectable_label(is_selected, device_label(device)).clicked() {
+                        ui_state.input_device = Some(device.clone());
+                        selection_changed = true;
+                    }
+                }
+            });
+        
+        if ui.small_button("⟳").on_hover_text("Refresh device list").clicked() {
+            ui_state.input_devices = list_input_devices();
+        }
+        
+        ui.separator();
+        
+        let mut retry = false;
+        match &status {
+            AudioStatus::Starting => {
+                ui.label("Starting audio input...");
+            }
+            AudioStatus::Running(device_name) => {
+                ui.colored_label(egui::Color32::GRAY, format!("Listening on {}", device_name));
+            }
+            AudioStatus::Error(message) => {
+                ui.colored_label(egui::Color32::RED, format!("Audio error: {}", message));
+                retry = ui.button("Retry").clicked();
+            }
+        }
+        
+        ui.separator();
+        
+        // Records the raw input plus a JSON sidecar of the scale and the detected pitches
+        if is_recording {
+            if ui.button("⏹ Stop").clicked() {
+                audio_state.lock().unwrap().record_request = None;
+            }
+            ui.colored_label(egui::Color32::RED, format!("Recording {:.1} s", record_elapsed.unwrap_or(0.0)));
+            if record_lost > 0.0 {
+                ui.colored_label(egui::Color32::YELLOW, format!("{:.1} s of input lost (silence in the file)", record_lost))
+                    .on_hover_text("Analysis fell behind the input; the gaps are listed in the JSON sidecar");
+            }
+        } else {
+            if ui.button("⏺ Record").on_hover_text("Record the input to a WAV file").clicked() {
+                let path = rfd::FileDialog::new()
+                    .add_filter("WAV audio", &["wav"])
+                    .set_file_name("lyretune-session.wav")
+                    .save_file();
+                if let Some(path) = path {
+                    let mut state = audio_state.lock().unwrap();
+                    state.record_error = None;
+                    state.record_request = Some(RecordRequest {
+                        path,
+                        scale: ui_state.scale_data.clone(),
+                    });
+                }
+            }
+            if let Some(message) = record_error {
+                ui.colored_label(egui::Color32::RED, message);
+            }
+        }
+        
+        if selection_changed || retry {
+            let mut state = audio_state.lock().unwrap();
+            state.input_device = ui_state.input_device.clone();
+            state.restart_requested = true;
+        }
+    });
+}
+
+fn show_recording_row(ui_state: &mut UiState, ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        ui.label("Recording:");
+        
+        if ui.button("Open WAV...").clicked() {
+            if let Some(path) = rfd::FileDialog::new().add_filter("WAV audio", &["wav"]).pick_file() {
+                ui_state.open_recording(&path);
+            }
+        }
+        
+        let mut close = false;
+        if let Some(recording) = &mut ui_state.recording {
+            ui.label(recording.recording.file_name());
+            
+            let duration = recording.recording.duration_secs();
+            ui.add(egui::Slider::new(&mut recording.position, 0.0..=duration)
+                .suffix(" s")
+                .fixed_decimals(2));
+            
+            close = ui.button("Close").on_hover_text("Return to live input").clicked();
+        } else if let Some(message) = &ui_state.recording_error {
+            ui.colored_label(egui::Color32::RED, message);
+        } else {
+            ui.colored_label(egui::Color32::GRAY, "Live input");
+        }
+        
+        if close {
+            ui_state.recording = None;
+        }
+    });
+}
+
 fn update_scale_data(ui_state: &mut UiState) {
     ui_state.scale_data = ScaleData::new(
         ui_state.scale_type,
@@ -471,12 +1070,133 @@ fn update_scale_data(ui_state: &mut UiState) {
         ui_state.num_strings,
         ui_state.temperament,
         ui_state.octave_offset,
+        &ui_state.reference_pitch,
+        ui_state.tonic_string,
     );
+    ui_state.inharmonicity = vec![None; ui_state.scale_data.frequencies.len()];
+    ui_state.current_string = None;
+    ui_state.string_tolerances.resize(ui_state.scale_data.frequencies.len(), None);
+    ui_state.needle_cents = None;
+}
+
+// One band per strobe partial, its stripes shifted by the band's phase: they stand still when the
+// string is in tune and drift right when sharp, left when flat (faster on the lower bands, which
+// follow higher partials). Bands fade with their partial's level and are blank without a reading.
+fn show_strobe(ui: &mut egui::Ui, strobe: Option<StrobePhase>) {
+    let size = egui::vec2(ui.available_width(), STROBE_BAND_HEIGHT * STROBE_PARTIALS.len() as f32);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::from_gray(20));
+    
+    let Some(strobe) = strobe else {
+        return;
+    };
+    let period = rect.width() / STROBE_STRIPES as f32;
+    for (band, (&phase, &level)) in strobe.phases.iter().zip(&strobe.levels).enumerate() {
+        let top = rect.top() + band as f32 * STROBE_BAND_HEIGHT;
+        let color = egui::Color32::from_rgb(255, 150, 0).gamma_multiply(0.2 + 0.8 * level);
+        for stripe in -1..=STROBE_STRIPES as i32 {
+            let left = rect.left() + (stripe as f32 + phase) * period;
+            let stripe_rect = egui::Rect::from_min_size(
+                egui::pos2(left, top + 1.0),
+                egui::vec2(period / 2.0, STROBE_BAND_HEIGHT - 2.0),
+            );
+            painter.rect_filled(stripe_rect, 0.0, color);
+        }
+    }
 }
 
+// Nearest string by musical distance, so a note halfway between two strings in cents (not in
+// Hz) is where the choice flips
 fn find_closest_note(scale_data: &ScaleData, frequency: f32) -> Option<(usize, f32)> {
+    if frequency <= 0.0 {
+        return None;
+    }
     scale_data.frequencies.iter()
         .enumerate()
-        .min_by_key(|(_, &f)| ((f - frequency).abs() * 1000.0) as i32)
+        .min_by(|(_, a), (_, b)| {
+            let distance = |f: f32| (f / frequency).log2().abs();
+            distance(**a).total_cmp(&distance(**b))
+        })
         .map(|(i, &f)| (i, f))
-}
\ No newline at end of file
+}
+
+// A ±50 cent scale with the in-tune zone shaded green and a needle at the smoothed deviation.
+// The triangle on the flat side (pointing up in pitch) or the sharp side (pointing down) lights
+// while the string is out of tune, and everything greys out once the string stops sounding.
+fn show_needle(ui: &mut egui::Ui, cents: Option<f32>, tolerance_cents: f32, heard: bool) {
+    let size = egui::vec2(ui.available_width(), NEEDLE_GAUGE_HEIGHT);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::from_gray(20));
+    
+    // Room for the arrows either side and the reading underneath
+    let arrow_width = NEEDLE_GAUGE_HEIGHT * 0.5;
+    let scale = egui::Rect::from_min_max(
+        egui::pos2(rect.left() + arrow_width, rect.top() + 8.0),
+        egui::pos2(rect.right() - arrow_width, rect.bottom() - 40.0),
+    );
+    let x_for = |cents: f32| {
+        scale.center().x + cents.clamp(-NEEDLE_RANGE_CENTS, NEEDLE_RANGE_CENTS) / NEEDLE_RANGE_CENTS * scale.width() / 2.0
+    };
+    
+    let zone = egui::Rect::from_x_y_ranges(x_for(-tolerance_cents)..=x_for(tolerance_cents), scale.y_range());
+    painter.rect_filled(zone, 0.0, egui::Color32::from_rgb(0, 90, 0));
+    for tick in (-50..=50).step_by(10) {
+        let x = x_for(tick as f32);
+        let top = if tick == 0 { scale.top() } else { scale.center().y };
+        painter.line_segment([egui::pos2(x, top), egui::pos2(x, scale.bottom())], egui::Stroke::new(1.5, egui::Color32::GRAY));
+        painter.text(
+            egui::pos2(x, scale.bottom() + 2.0),
+            egui::Align2::CENTER_TOP,
+            format!("{:+}", tick),
+            egui::FontId::proportional(12.0),
+            egui::Color32::GRAY,
+        );
+    }
+    
+    let Some(cents) = cents else {
+        return;
+    };
+    let in_tune = cents.abs() <= tolerance_cents;
+    let color = if !heard {
+        egui::Color32::GRAY
+    } else if in_tune {
+        egui::Color32::GREEN
+    } else {
+        egui::Color32::from_rgb(255, 150, 0)
+    };
+    
+    let x = x_for(cents);
+    painter.line_segment([egui::pos2(x, scale.top()), egui::pos2(x, scale.bottom())], egui::Stroke::new(5.0, color));
+    painter.text(
+        egui::pos2(scale.center().x, rect.bottom() - 2.0),
+        egui::Align2::CENTER_BOTTOM,
+        format!("{:+.1}¢", cents),
+        egui::FontId::proportional(22.0),
+        color,
+    );
+    
+    let arrow_color = |lit: bool| if lit && heard { color } else { egui::Color32::from_gray(50) };
+    let (middle, half) = (scale.center().y, arrow_width * 0.3);
+    let flat_x = rect.left() + arrow_width / 2.0;
+    painter.add(egui::Shape::convex_polygon(
+        vec![
+            egui::pos2(flat_x - half, middle - half),
+            egui::pos2(flat_x + half, middle),
+            egui::pos2(flat_x - half, middle + half),
+        ],
+        arrow_color(!in_tune && cents < 0.0),
+        egui::Stroke::NONE,
+    ));
+    let sharp_x = rect.right() - arrow_width / 2.0;
+    painter.add(egui::Shape::convex_polygon(
+        vec![
+            egui::pos2(sharp_x + half, middle - half),
+            egui::pos2(sharp_x - half, middle),
+            egui::pos2(sharp_x + half, middle + half),
+        ],
+        arrow_color(!in_tune && cents > 0.0),
+        egui::Stroke::NONE,
+    ));
+}
@@ -0,0 +1,207 @@
+// WAV files: recordings loaded for offline analysis, and tuning sessions recorded from the
+// live input. Samples are interleaved f32 in [-1, 1], the form the input callback produces.
+
+use crate::scales::ScaleData;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+pub struct WavRecording {
+    pub path: PathBuf,
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub samples: Vec<f32>,
+}
+
+impl WavRecording {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let reader = hound::WavReader::open(path)
+            .map_err(|e| format!("Failed to open '{}': {}", path.display(), e))?;
+        let spec = reader.spec();
+        
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<Vec<_>, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader.into_samples::<i32>()
+                    .map(|sample| sample.map(|s| s as f32 * scale))
+                    .collect::<Result<Vec<_>, _>>()
+            }
+        }.map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+        
+        Ok(Self {
+            path: path.to_path_buf(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels.max(1) as usize,
+            samples,
+        })
+    }
+    
+    pub fn file_name(&self) -> String {
+        self.path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+    
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+    
+    pub fn duration_secs(&self) -> f32 {
+        self.frames() as f32 / self.sample_rate as f32
+    }
+}
+
+// Written next to a session recording (same name, `.json`) so a replay knows what was being
+// tuned and what the tuner showed at the time
+#[derive(Serialize, Deserialize)]
+pub struct SessionSidecar {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub scale: ScaleData,
+    pub pitches: Vec<PitchFrame>,
+    // Stretches where the input couldn't be kept up with; the WAV holds silence there
+    pub gaps: Vec<RecordingGap>,
+}
+
+// One analysis frame; `time` is the end of the analysis window in seconds from the start
+#[derive(Serialize, Deserialize)]
+pub struct PitchFrame {
+    pub time: f32,
+    pub frequency: f32,
+    pub confidence: f32,
+}
+
+// `time` and `duration` in seconds from the start
+#[derive(Serialize, Deserialize)]
+pub struct RecordingGap {
+    pub time: f32,
+    pub duration: f32,
+}
+
+// Writes the raw input, all channels, as 32-bit float WAV while collecting the sidecar
+pub struct SessionRecorder {
+    writer: hound::WavWriter<BufWriter<File>>,
+    sidecar_path: PathBuf,
+    sidecar: SessionSidecar,
+    samples_written: usize,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path, sample_rate: u32, channels: u16, scale: ScaleData) -> Result<Self, String> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let writer = hound::WavWriter::create(path, spec)
+            .map_err(|e| format!("Failed to create '{}': {}", path.display(), e))?;
+        
+        Ok(Self {
+            writer,
+            sidecar_path: path.with_extension("json"),
+            sidecar: SessionSidecar {
+                sample_rate,
+                channels,
+                scale,
+                pitches: Vec::new(),
+                gaps: Vec::new(),
+            },
+            samples_written: 0,
+        })
+    }
+    
+    pub fn write_frame(&mut self, frame: &[f32]) -> Result<(), String> {
+        for &sample in frame {
+            self.writer.write_sample(sample).map_err(|e| format!("Failed to write recording: {}", e))?;
+        }
+        self.samples_written += frame.len();
+        Ok(())
+    }
+    
+    pub fn add_pitch(&mut self, frequency: f32, confidence: f32) {
+        let time = self.elapsed_secs();
+        self.sidecar.pitches.push(PitchFrame { time, frequency, confidence });
+    }
+    
+    // Marks the next `frames` as silence standing in for lost input
+    pub fn add_gap(&mut self, frames: usize) {
+        let time = self.elapsed_secs();
+        let duration = frames as f32 / self.sidecar.sample_rate as f32;
+        self.sidecar.gaps.push(RecordingGap { time, duration });
+    }
+    
+    pub fn lost_secs(&self) -> f32 {
+        self.sidecar.gaps.iter().map(|gap| gap.duration).sum()
+    }
+    
+    pub fn elapsed_secs(&self) -> f32 {
+        let frames = self.samples_written / self.sidecar.channels.max(1) as usize;
+        frames as f32 / self.sidecar.sample_rate as f32
+    }
+    
+    pub fn finish(self) -> Result<(), String> {
+        self.writer.finalize().map_err(|e| format!("Failed to finish recording: {}", e))?;
+        
+        let file = File::create(&self.sidecar_path)
+            .map_err(|e| format!("Failed to create '{}': {}", self.sidecar_path.display(), e))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.sidecar)
+            .map_err(|e| format!("Failed to write '{}': {}", self.sidecar_path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scales::{Mode, ReferencePitch, ScaleType, Temperament};
+    
+    #[test]
+    fn test_session_recorder_round_trip() {
+        let scale = ScaleData::new(ScaleType::Modes, Some(Mode::Dorios), None, "E", 7, Temperament::Equal, 0, &ReferencePitch::default(), 0);
+        let path = std::env::temp_dir().join(format!("lyretune-recorder-test-{}.wav", std::process::id()));
+        let mut recorder = SessionRecorder::create(&path, 8000, 2, scale.clone()).unwrap();
+        
+        // 0.1 s of input, a reading, then 0.05 s lost and padded with silence, then 0.1 s more
+        for _ in 0..800 {
+            recorder.write_frame(&[0.5, -0.25]).unwrap();
+        }
+        recorder.add_pitch(196.0, 0.9);
+        recorder.add_gap(400);
+        for _ in 0..400 {
+            recorder.write_frame(&[0.0, 0.0]).unwrap();
+        }
+        for _ in 0..800 {
+            recorder.write_frame(&[0.5, -0.25]).unwrap();
+        }
+        assert_eq!(recorder.lost_secs(), 0.05);
+        assert_eq!(recorder.elapsed_secs(), 0.25);
+        recorder.finish().unwrap();
+        
+        let recording = WavRecording::open(&path).unwrap();
+        let sidecar_path = path.with_extension("json");
+        let sidecar: SessionSidecar = serde_json::from_reader(File::open(&sidecar_path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&sidecar_path).unwrap();
+        
+        assert_eq!(recording.sample_rate, 8000);
+        assert_eq!(recording.channels, 2);
+        assert_eq!(recording.frames(), 2000);
+        let frames: Vec<&[f32]> = recording.samples.chunks(2).collect();
+        assert!(frames[..800].iter().all(|frame| *frame == [0.5, -0.25]));
+        assert!(frames[800..1200].iter().all(|frame| *frame == [0.0, 0.0]));
+        assert!(frames[1200..].iter().all(|frame| *frame == [0.5, -0.25]));
+        
+        assert_eq!(sidecar.sample_rate, 8000);
+        assert_eq!(sidecar.channels, 2);
+        assert_eq!(sidecar.scale.frequencies, scale.frequencies);
+        assert_eq!(sidecar.pitches.len(), 1);
+        assert_eq!(sidecar.pitches[0].time, 0.1);
+        assert_eq!(sidecar.pitches[0].frequency, 196.0);
+        assert_eq!(sidecar.pitches[0].confidence, 0.9);
+        assert_eq!(sidecar.gaps.len(), 1);
+        assert_eq!(sidecar.gaps[0].time, 0.1);
+        assert_eq!(sidecar.gaps[0].duration, 0.05);
+    }
+}